@@ -0,0 +1,216 @@
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use crate::boundary::Area;
+use crate::config::COVERAGE_HISTORY_PATH;
+use crate::gpx::Activity;
+use crate::matching::COVERED_THRESHOLD;
+use crate::region::{Region, RegionConfig};
+use crate::tiles::Provider;
+use crate::{export, grid, matching, osm, render, tiles};
+
+pub struct BatchOptions<'a> {
+    /// Each region's PNG is written next to this path, as `<id>.png`.
+    pub output: &'a str,
+    /// Gets one `<id>.json` per region plus `index.json`.
+    pub web_dir: &'a str,
+    pub grid_size: f64,
+    /// Override the regions' own zoom and tile provider.
+    pub zoom: Option<u32>,
+    pub provider: Option<Provider>,
+}
+
+/// Covered and total trail length of a region.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CoverageStats {
+    date: String,
+    covered_km: f64,
+    total_km: f64,
+}
+
+impl CoverageStats {
+    fn pct(&self) -> f64 {
+        if self.total_km > 0.0 {
+            self.covered_km / self.total_km * 100.0
+        } else {
+            0.0
+        }
+    }
+}
+
+/// Resolve the area of every configured region, in config order. A region whose area
+/// can't be loaded (e.g. its boundary fails to download) is reported and left out.
+pub async fn load_areas<'a>(
+    client: &reqwest::Client,
+    config: &'a RegionConfig,
+) -> Result<(Vec<&'a Region>, Vec<Area>)> {
+    let mut regions = Vec::with_capacity(config.regions.len());
+    let mut areas = Vec::with_capacity(config.regions.len());
+    for region in config.regions.values() {
+        match region.load_area(client).await {
+            Ok(area) => {
+                regions.push(region);
+                areas.push(area);
+            }
+            Err(e) => eprintln!("Warning: skipping region '{}': {e:#}", region.id),
+        }
+    }
+    if regions.is_empty() {
+        bail!(
+            "None of the {} configured regions could be loaded",
+            config.regions.len()
+        );
+    }
+    Ok((regions, areas))
+}
+
+/// Match, export and render each region (with its area from `load_areas`) against one
+/// set of activities, then write the combined `index.json` and print a coverage summary.
+///
+/// A region that fails is reported and left out; the others still run.
+pub async fn update_all(
+    client: &reqwest::Client,
+    regions: &[&Region],
+    areas: &[Area],
+    activities: &[Activity],
+    options: &BatchOptions<'_>,
+) -> Result<()> {
+    let mut history = load_history()?;
+    let mut summary = Vec::new();
+    let mut index = Vec::new();
+
+    for (&region, area) in regions.iter().zip(areas) {
+        eprintln!("\n=== {} ({}) ===", region.title, region.id);
+        let stats = match update_region(client, region, area, activities, options).await {
+            Ok(stats) => stats,
+            Err(e) => {
+                eprintln!("Warning: skipping region '{}': {e:#}", region.id);
+                continue;
+            }
+        };
+        index.push(json!({
+            "id": region.id,
+            "title": region.title,
+            "data": format!("{}.json", region.id),
+            "bbox": [area.bounds.west, area.bounds.south, area.bounds.east, area.bounds.north],
+            "covered_km": stats.covered_km,
+            "total_km": stats.total_km,
+        }));
+        let previous = history.insert(region.id.clone(), stats.clone());
+        summary.push((region, stats, previous));
+    }
+
+    let index_path = Path::new(options.web_dir).join("index.json");
+    fs::create_dir_all(options.web_dir)?;
+    fs::write(
+        &index_path,
+        serde_json::to_string(&json!({ "regions": index }))?,
+    )
+    .with_context(|| format!("Failed to write {}", index_path.display()))?;
+    eprintln!("\nWrote {}", index_path.display());
+
+    save_history(&history)?;
+    print_summary(&summary);
+    Ok(())
+}
+
+async fn update_region(
+    client: &reqwest::Client,
+    region: &Region,
+    area: &Area,
+    activities: &[Activity],
+    options: &BatchOptions<'_>,
+) -> Result<CoverageStats> {
+    let (_trails, segments) = osm::fetch_trails(client, region, area).await?;
+    let coverage = matching::compute_coverage(&segments, activities);
+    let grid_result = grid::compute_grid(&segments, &coverage, options.grid_size, area);
+
+    let json_output = Path::new(options.web_dir).join(format!("{}.json", region.id));
+    export::export_json(
+        &segments,
+        &coverage,
+        &grid_result,
+        region,
+        area,
+        &json_output.to_string_lossy(),
+    )?;
+
+    let tile_map = tiles::fetch_and_stitch(
+        client,
+        &area.bounds,
+        options.zoom.unwrap_or(region.zoom),
+        options.provider.unwrap_or(region.tile_provider),
+    )
+    .await?;
+    let png_output = Path::new(options.output).with_file_name(format!("{}.png", region.id));
+    render::render_png(
+        &tile_map,
+        &segments,
+        &coverage,
+        &region.title,
+        &png_output.to_string_lossy(),
+    )?;
+
+    // fold from 0.0 rather than sum(), which gives -0.0 for no segments
+    let total_km = coverage.iter().fold(0.0, |km, c| km + c.length_m / 1000.0);
+    let covered_km = coverage
+        .iter()
+        .filter(|c| c.coverage_pct >= COVERED_THRESHOLD)
+        .fold(0.0, |km, c| km + c.length_m / 1000.0);
+    Ok(CoverageStats {
+        date: chrono::Local::now().format("%Y-%m-%d %H:%M").to_string(),
+        covered_km,
+        total_km,
+    })
+}
+
+/// One line per region, with the change in covered percentage since the previous run.
+fn print_summary(summary: &[(&Region, CoverageStats, Option<CoverageStats>)]) {
+    let width = summary
+        .iter()
+        .map(|(region, ..)| region.title.chars().count())
+        .max()
+        .unwrap_or(0)
+        .max("Region".len());
+
+    eprintln!(
+        "\n{:<width$}  {:>9}  {:>9}  {:>6}  Change",
+        "Region", "Covered", "Total", "%"
+    );
+    for (region, stats, previous) in summary {
+        let change = match previous {
+            Some(prev) => format!("{:+.1} pts since {}", stats.pct() - prev.pct(), prev.date),
+            None => "new".to_string(),
+        };
+        eprintln!(
+            "{:<width$}  {:>6.1} km  {:>6.1} km  {:>5.1}%  {}",
+            region.title,
+            stats.covered_km,
+            stats.total_km,
+            stats.pct(),
+            change
+        );
+    }
+}
+
+fn load_history() -> Result<BTreeMap<String, CoverageStats>> {
+    let path = Path::new(COVERAGE_HISTORY_PATH);
+    if !path.exists() {
+        return Ok(BTreeMap::new());
+    }
+    let json = fs::read_to_string(path)?;
+    serde_json::from_str(&json).with_context(|| format!("Failed to parse {COVERAGE_HISTORY_PATH}"))
+}
+
+fn save_history(history: &BTreeMap<String, CoverageStats>) -> Result<()> {
+    let path = Path::new(COVERAGE_HISTORY_PATH);
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, serde_json::to_string_pretty(history)?)?;
+    Ok(())
+}
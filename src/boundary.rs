@@ -0,0 +1,300 @@
+use anyhow::{Context, Result, bail};
+use geo::{BooleanOps, BoundingRect, Intersects};
+use geo_types::{Coord, LineString, MultiLineString, MultiPolygon, Polygon};
+use serde_json::{Value, json};
+use std::fs;
+use std::path::Path;
+
+use crate::region::Bounds;
+
+/// Where trails and tracks count for a region: its bounding box, cut down to a polygon
+/// boundary if it has one.
+pub struct Area {
+    pub bounds: Bounds,
+    pub boundary: Option<MultiPolygon<f64>>,
+}
+
+impl Area {
+    pub fn contains(&self, lat: f64, lon: f64) -> bool {
+        self.bounds.contains(lat, lon)
+            && self
+                .boundary
+                .as_ref()
+                .is_none_or(|b| b.intersects(&Coord { x: lon, y: lat }))
+    }
+
+    /// The parts of a line inside the boundary (the whole line without one).
+    pub fn clip(&self, line: &LineString<f64>) -> Vec<LineString<f64>> {
+        match &self.boundary {
+            Some(boundary) => {
+                boundary
+                    .clip(&MultiLineString::new(vec![line.clone()]), false)
+                    .0
+            }
+            None => vec![line.clone()],
+        }
+    }
+}
+
+/// The bounding box of a boundary, or `None` if it's empty.
+pub fn bounds_of(boundary: &MultiPolygon<f64>) -> Option<Bounds> {
+    let rect = boundary.bounding_rect()?;
+    Some(Bounds {
+        south: rect.min().y,
+        west: rect.min().x,
+        north: rect.max().y,
+        east: rect.max().x,
+    })
+}
+
+/// Read every `Polygon` and `MultiPolygon` in a GeoJSON file, whether bare, in a `Feature`
+/// or in a `FeatureCollection`.
+pub fn read_geojson(path: &Path) -> Result<MultiPolygon<f64>> {
+    let text =
+        fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    let json: Value = serde_json::from_str(&text)
+        .with_context(|| format!("Failed to parse {}", path.display()))?;
+
+    from_geojson(&json)
+        .with_context(|| format!("No Polygon or MultiPolygon found in {}", path.display()))
+}
+
+/// Every `Polygon` and `MultiPolygon` in a GeoJSON value, or `None` if there are none.
+pub fn from_geojson(json: &Value) -> Option<MultiPolygon<f64>> {
+    let mut polygons = Vec::new();
+    collect_polygons(json, &mut polygons);
+    (!polygons.is_empty()).then(|| MultiPolygon::new(polygons))
+}
+
+pub fn write_geojson(path: &Path, boundary: &MultiPolygon<f64>) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let json = serde_json::to_string(&to_geojson(boundary))?;
+    fs::write(path, json).with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// A boundary as a GeoJSON `MultiPolygon` geometry.
+pub fn to_geojson(boundary: &MultiPolygon<f64>) -> Value {
+    let ring = |ring: &LineString<f64>| -> Vec<Value> {
+        ring.0.iter().map(|c| json!([c.x, c.y])).collect()
+    };
+    let polygons: Vec<Value> = boundary
+        .0
+        .iter()
+        .map(|polygon| {
+            let mut rings = vec![ring(polygon.exterior())];
+            rings.extend(polygon.interiors().iter().map(ring));
+            json!(rings)
+        })
+        .collect();
+    json!({
+        "type": "MultiPolygon",
+        "coordinates": polygons,
+    })
+}
+
+fn collect_polygons(geometry: &Value, polygons: &mut Vec<Polygon<f64>>) {
+    match geometry["type"].as_str() {
+        Some("Polygon") => polygons.extend(parse_polygon(&geometry["coordinates"])),
+        Some("MultiPolygon") => {
+            for polygon in geometry["coordinates"].as_array().into_iter().flatten() {
+                polygons.extend(parse_polygon(polygon));
+            }
+        }
+        Some("GeometryCollection") => {
+            for g in geometry["geometries"].as_array().into_iter().flatten() {
+                collect_polygons(g, polygons);
+            }
+        }
+        Some("Feature") => collect_polygons(&geometry["geometry"], polygons),
+        Some("FeatureCollection") => {
+            for f in geometry["features"].as_array().into_iter().flatten() {
+                collect_polygons(f, polygons);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// The first ring is the exterior, the rest are holes.
+fn parse_polygon(rings: &Value) -> Option<Polygon<f64>> {
+    let mut rings = rings.as_array()?.iter().map(parse_ring);
+    let exterior = rings.next()??;
+    Some(Polygon::new(exterior, rings.flatten().collect()))
+}
+
+fn parse_ring(ring: &Value) -> Option<LineString<f64>> {
+    let coords: Vec<Coord<f64>> = ring
+        .as_array()?
+        .iter()
+        .filter_map(|c| {
+            Some(Coord {
+                x: c.get(0)?.as_f64()?,
+                y: c.get(1)?.as_f64()?,
+            })
+        })
+        .collect();
+    (coords.len() >= 4).then(|| LineString::new(coords))
+}
+
+/// Build a boundary from the member ways of an OSM multipolygon relation. Ways only
+/// share end nodes, so they are chained into closed rings first, then each inner ring
+/// becomes a hole in the outer ring that contains it.
+pub fn from_ways(
+    outer: Vec<Vec<Coord<f64>>>,
+    inner: Vec<Vec<Coord<f64>>>,
+) -> Result<MultiPolygon<f64>> {
+    let mut polygons: Vec<Polygon<f64>> = assemble_rings(outer)?
+        .into_iter()
+        .map(|ring| Polygon::new(ring, vec![]))
+        .collect();
+    if polygons.is_empty() {
+        bail!("Boundary has no outer ways");
+    }
+
+    for hole in assemble_rings(inner)? {
+        let Some(polygon) = polygons.iter_mut().find(|p| p.intersects(&hole.0[0])) else {
+            eprintln!("Warning: ignoring boundary hole outside every outer ring");
+            continue;
+        };
+        polygon.interiors_push(hole);
+    }
+    Ok(MultiPolygon::new(polygons))
+}
+
+fn assemble_rings(mut ways: Vec<Vec<Coord<f64>>>) -> Result<Vec<LineString<f64>>> {
+    ways.retain(|way| way.len() >= 2);
+    let mut rings = Vec::new();
+
+    while let Some(mut ring) = ways.pop() {
+        while ring.first() != ring.last() {
+            let end = *ring.last().unwrap();
+            let Some(i) = ways
+                .iter()
+                .position(|w| w.first() == Some(&end) || w.last() == Some(&end))
+            else {
+                bail!(
+                    "Boundary ring doesn't close (open end at {:.5}, {:.5})",
+                    end.y,
+                    end.x
+                );
+            };
+            let mut next = ways.swap_remove(i);
+            if next.first() != Some(&end) {
+                next.reverse();
+            }
+            ring.extend(next.into_iter().skip(1));
+        }
+        if ring.len() >= 4 {
+            rings.push(LineString::new(ring));
+        }
+    }
+    Ok(rings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn way(points: &[(f64, f64)]) -> Vec<Coord<f64>> {
+        points.iter().map(|&(x, y)| Coord { x, y }).collect()
+    }
+
+    fn area(boundary: MultiPolygon<f64>) -> Area {
+        Area {
+            bounds: bounds_of(&boundary).unwrap(),
+            boundary: Some(boundary),
+        }
+    }
+
+    #[test]
+    fn chains_reversed_and_unordered_ways_into_a_ring() {
+        let outer = vec![
+            way(&[(0.0, 0.0), (1.0, 0.0)]),
+            way(&[(0.0, 1.0), (1.0, 1.0)]),
+            way(&[(0.0, 0.0), (0.0, 0.5), (0.0, 1.0)]),
+            way(&[(1.0, 0.0), (1.0, 1.0)]),
+        ];
+        let boundary = from_ways(outer, vec![]).unwrap();
+        assert_eq!(boundary.0.len(), 1);
+        let ring = boundary.0[0].exterior();
+        assert_eq!(ring.0.len(), 6);
+        assert_eq!(ring.0.first(), ring.0.last());
+
+        let area = area(boundary);
+        assert!(area.contains(0.5, 0.5));
+        assert!(!area.contains(0.5, 1.5));
+    }
+
+    #[test]
+    fn fails_on_a_ring_that_does_not_close() {
+        let outer = vec![
+            way(&[(0.0, 0.0), (1.0, 0.0)]),
+            way(&[(1.0, 0.0), (1.0, 1.0)]),
+            way(&[(1.0, 1.0), (0.0, 1.0)]),
+        ];
+        let error = from_ways(outer, vec![]).unwrap_err().to_string();
+        assert!(error.contains("doesn't close"), "{error}");
+
+        assert!(from_ways(vec![], vec![]).is_err());
+    }
+
+    #[test]
+    fn puts_inner_rings_as_holes() {
+        let outer = vec![way(&[
+            (0.0, 0.0),
+            (4.0, 0.0),
+            (4.0, 4.0),
+            (0.0, 4.0),
+            (0.0, 0.0),
+        ])];
+        let inner = vec![
+            way(&[(1.0, 1.0), (2.0, 1.0), (2.0, 2.0)]),
+            way(&[(1.0, 1.0), (1.0, 2.0), (2.0, 2.0)]),
+            // Outside every outer ring, so ignored
+            way(&[(5.0, 5.0), (6.0, 5.0), (6.0, 6.0), (5.0, 5.0)]),
+        ];
+        let boundary = from_ways(outer, inner).unwrap();
+        assert_eq!(boundary.0[0].interiors().len(), 1);
+
+        let area = area(boundary);
+        assert!(area.contains(3.0, 3.0));
+        assert!(!area.contains(1.5, 1.5));
+    }
+
+    #[test]
+    fn clips_lines_to_the_boundary() {
+        let outer = vec![way(&[
+            (0.0, 0.0),
+            (4.0, 0.0),
+            (4.0, 4.0),
+            (0.0, 4.0),
+            (0.0, 0.0),
+        ])];
+        let inner = vec![way(&[
+            (1.0, 1.0),
+            (2.0, 1.0),
+            (2.0, 2.0),
+            (1.0, 2.0),
+            (1.0, 1.0),
+        ])];
+        let area = area(from_ways(outer, inner).unwrap());
+
+        // Entering from outside, crossing the hole and leaving again
+        let line = LineString::from(vec![(-1.0, 1.5), (5.0, 1.5)]);
+        let mut pieces: Vec<Vec<(f64, f64)>> = area
+            .clip(&line)
+            .iter()
+            .map(|piece| piece.0.iter().map(|c| (c.x, c.y)).collect())
+            .collect();
+        pieces.sort_by(|a, b| a[0].0.total_cmp(&b[0].0));
+        assert_eq!(
+            pieces,
+            [vec![(0.0, 1.5), (1.0, 1.5)], vec![(2.0, 1.5), (4.0, 1.5)]]
+        );
+
+        let outside = LineString::from(vec![(5.0, 5.0), (6.0, 6.0)]);
+        assert!(area.clip(&outside).is_empty());
+    }
+}
@@ -0,0 +1,366 @@
+use crate::geo_util::{EARTH_RADIUS_M, haversine_m};
+use crate::gpx::{Activity, Track, TrackPoint};
+
+/// Faster than anyone runs or rides down a forest track (~90 km/h).
+const MAX_SPEED_MPS: f64 = 25.0;
+/// Without timestamps, a jump this far out and back between consecutive points is a spike.
+const MAX_JUMP_M: f64 = 200.0;
+/// After this many rejected points in a row, assume the track really moved (e.g. after
+/// losing signal in a gorge) and accept the next point.
+const MAX_CONSECUTIVE_REJECTS: usize = 5;
+/// Points staying within this radius are jitter around a stop.
+const STATIONARY_RADIUS_M: f64 = 15.0;
+/// A stop must last this long to be collapsed (or this many points, without timestamps).
+const STATIONARY_MIN_S: i64 = 30;
+const STATIONARY_MIN_POINTS: usize = 30;
+/// ...and be no further than this from where it started by then. A slow climb stays within
+/// the radius for as long, but keeps drifting away.
+const STATIONARY_MAX_DRIFT_M: f64 = STATIONARY_RADIUS_M / 2.0;
+/// Kalman filter noise: GPS measurement error and how far a walker can drift per second.
+const KALMAN_MEASUREMENT_VAR_M2: f64 = 25.0;
+const KALMAN_PROCESS_VAR_M2_PER_S: f64 = 4.0;
+
+/// How many points each step removed from one activity.
+#[derive(Debug, Default)]
+pub struct CleaningReport {
+    pub input_points: usize,
+    pub spikes: usize,
+    pub stationary: usize,
+}
+
+/// Remove GPS spikes and stop jitter from every activity, optionally smoothing the rest.
+pub fn clean_activities(activities: Vec<Activity>, smooth: bool) -> Vec<Activity> {
+    let mut total = CleaningReport::default();
+
+    let cleaned: Vec<Activity> = activities
+        .into_iter()
+        .filter_map(|mut activity| {
+            let mut report = CleaningReport::default();
+            activity.tracks = activity
+                .tracks
+                .iter()
+                .map(|track| clean_track(track, smooth, &mut report))
+                .filter(|track| track.geometry.0.len() >= 2)
+                .collect();
+
+            if report.spikes + report.stationary > 0 {
+                eprintln!(
+                    "Cleaned {} — dropped {} spikes, collapsed {} stationary points ({:.1}% of {})",
+                    activity.name,
+                    report.spikes,
+                    report.stationary,
+                    (report.spikes + report.stationary) as f64 / report.input_points as f64 * 100.0,
+                    report.input_points,
+                );
+            }
+            total.input_points += report.input_points;
+            total.spikes += report.spikes;
+            total.stationary += report.stationary;
+
+            if activity.tracks.is_empty() {
+                eprintln!("Dropping {} — nothing left after cleaning", activity.name);
+                return None;
+            }
+            Some(activity)
+        })
+        .collect();
+
+    eprintln!(
+        "Cleaning: {} spikes and {} stationary points removed from {} points{}",
+        total.spikes,
+        total.stationary,
+        total.input_points,
+        if smooth { ", smoothed" } else { "" },
+    );
+    cleaned
+}
+
+fn clean_track(track: &Track, smooth: bool, report: &mut CleaningReport) -> Track {
+    let points = track.points();
+    report.input_points += points.len();
+
+    let points = drop_spikes(&points, report);
+    let mut points = collapse_stationary(&points, report);
+    if smooth {
+        kalman_smooth(&mut points);
+    }
+    Track::from_points(&points)
+}
+
+/// Drop points that could only be reached from the previous kept point at an impossible
+/// speed. Without timestamps, only drop out-and-back jumps: a point far from both its
+/// neighbours while they are close to each other. The first point has no previous one, so
+/// it is dropped if it can't reach the second while the second can reach the third.
+fn drop_spikes(points: &[TrackPoint], report: &mut CleaningReport) -> Vec<TrackPoint> {
+    let mut kept: Vec<TrackPoint> = Vec::with_capacity(points.len());
+    let mut rejected_in_row = 0;
+
+    let skip = usize::from(first_is_spike(points));
+    report.spikes += skip;
+
+    for (i, &point) in points.iter().enumerate().skip(skip) {
+        let Some(prev) = kept.last() else {
+            kept.push(point);
+            continue;
+        };
+
+        let dist = haversine_m(prev.lat, prev.lon, point.lat, point.lon);
+        let impossible = match too_fast(prev, &point) {
+            Some(too_fast) => too_fast,
+            None => points.get(i + 1).is_some_and(|next| {
+                dist > MAX_JUMP_M
+                    && haversine_m(point.lat, point.lon, next.lat, next.lon) > MAX_JUMP_M
+                    && haversine_m(prev.lat, prev.lon, next.lat, next.lon) <= MAX_JUMP_M
+            }),
+        };
+
+        if impossible && rejected_in_row < MAX_CONSECUTIVE_REJECTS {
+            rejected_in_row += 1;
+            report.spikes += 1;
+        } else {
+            rejected_in_row = 0;
+            kept.push(point);
+        }
+    }
+
+    kept
+}
+
+/// Whether going from `a` to `b` takes an impossible speed, or `None` without both times.
+fn too_fast(a: &TrackPoint, b: &TrackPoint) -> Option<bool> {
+    let dt = (b.time? - a.time?).num_milliseconds() as f64 / 1000.0;
+    Some(dt > 0.0 && haversine_m(a.lat, a.lon, b.lat, b.lon) / dt > MAX_SPEED_MPS)
+}
+
+fn first_is_spike(points: &[TrackPoint]) -> bool {
+    let [first, second, third, ..] = points else {
+        return false;
+    };
+    let jump = |a: &TrackPoint, b: &TrackPoint| {
+        too_fast(a, b).unwrap_or_else(|| haversine_m(a.lat, a.lon, b.lat, b.lon) > MAX_JUMP_M)
+    };
+    jump(first, second) && !jump(second, third)
+}
+
+/// Replace runs of points that stay within `STATIONARY_RADIUS_M` of where they started,
+/// for long enough to be a stop and without drifting away, with their centroid.
+fn collapse_stationary(points: &[TrackPoint], report: &mut CleaningReport) -> Vec<TrackPoint> {
+    let mut result = Vec::with_capacity(points.len());
+    let mut i = 0;
+
+    while i < points.len() {
+        let anchor = points[i];
+        let mut end = i + 1;
+        while end < points.len()
+            && haversine_m(anchor.lat, anchor.lon, points[end].lat, points[end].lon)
+                <= STATIONARY_RADIUS_M
+        {
+            end += 1;
+        }
+
+        let run = &points[i..end];
+        // Where the run is once it has lasted long enough to be a stop
+        let settled = match anchor.time {
+            Some(t0) => run.iter().find(|p| {
+                p.time
+                    .is_some_and(|t| (t - t0).num_seconds() >= STATIONARY_MIN_S)
+            }),
+            None => run.get(STATIONARY_MIN_POINTS - 1),
+        };
+        let is_stop = settled.is_some_and(|p| {
+            haversine_m(anchor.lat, anchor.lon, p.lat, p.lon) <= STATIONARY_MAX_DRIFT_M
+        });
+
+        if is_stop {
+            let n = run.len() as f64;
+            let mut centroid = anchor;
+            centroid.lon = run.iter().map(|p| p.lon).sum::<f64>() / n;
+            centroid.lat = run.iter().map(|p| p.lat).sum::<f64>() / n;
+            result.push(centroid);
+            report.stationary += run.len() - 1;
+            i = end;
+        } else {
+            result.push(anchor);
+            i += 1;
+        }
+    }
+
+    result
+}
+
+/// Smooth positions with a constant-position Kalman filter in local meters.
+fn kalman_smooth(points: &mut [TrackPoint]) {
+    let Some(origin) = points.first().copied() else {
+        return;
+    };
+    let m_per_deg_lat = EARTH_RADIUS_M.to_radians();
+    let m_per_deg_lon = m_per_deg_lat * origin.lat.to_radians().cos();
+
+    let (mut x, mut y) = (0.0, 0.0);
+    let mut variance = KALMAN_MEASUREMENT_VAR_M2;
+    let mut prev_time = origin.time;
+
+    for point in points.iter_mut() {
+        let dt = match (prev_time, point.time) {
+            (Some(t0), Some(t1)) => ((t1 - t0).num_milliseconds() as f64 / 1000.0).max(0.0),
+            _ => 1.0,
+        };
+        prev_time = point.time;
+
+        // Predict: uncertainty grows with elapsed time
+        variance += KALMAN_PROCESS_VAR_M2_PER_S * dt;
+
+        // Update with the measured position
+        let mx = (point.lon - origin.lon) * m_per_deg_lon;
+        let my = (point.lat - origin.lat) * m_per_deg_lat;
+        let gain = variance / (variance + KALMAN_MEASUREMENT_VAR_M2);
+        x += gain * (mx - x);
+        y += gain * (my - y);
+        variance *= 1.0 - gain;
+
+        point.lon = origin.lon + x / m_per_deg_lon;
+        point.lat = origin.lat + y / m_per_deg_lat;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{DateTime, Duration, Utc};
+
+    const M_PER_DEG: f64 = 111_195.0;
+
+    fn start() -> DateTime<Utc> {
+        "2026-06-30T08:00:00Z".parse().unwrap()
+    }
+
+    /// `n` points heading north `step_m` apart, `dt_s` seconds apart if timed.
+    fn walk(n: usize, step_m: f64, dt_s: Option<i64>) -> Vec<TrackPoint> {
+        (0..n)
+            .map(|i| TrackPoint {
+                lon: 5.1,
+                lat: 44.65 + i as f64 * step_m / M_PER_DEG,
+                time: dt_s.map(|dt| start() + Duration::seconds(i as i64 * dt)),
+                ele: Some(400.0 + i as f64),
+            })
+            .collect()
+    }
+
+    fn moved_east(mut point: TrackPoint, m: f64) -> TrackPoint {
+        point.lon += m / (M_PER_DEG * point.lat.to_radians().cos());
+        point
+    }
+
+    fn lats(points: &[TrackPoint]) -> Vec<f64> {
+        points.iter().map(|p| p.lat).collect()
+    }
+
+    #[test]
+    fn drops_a_spike_mid_track() {
+        for dt_s in [Some(10), None] {
+            let track = walk(10, 20.0, dt_s);
+            let mut points = track.clone();
+            points[5] = moved_east(points[5], 2000.0);
+
+            let mut report = CleaningReport::default();
+            let kept = drop_spikes(&points, &mut report);
+            assert_eq!(report.spikes, 1, "{dt_s:?}");
+            let mut expected = track;
+            expected.remove(5);
+            assert_eq!(lats(&kept), lats(&expected), "{dt_s:?}");
+        }
+    }
+
+    #[test]
+    fn drops_a_spike_at_the_start() {
+        for dt_s in [Some(10), None] {
+            let track = walk(10, 20.0, dt_s);
+            let mut points = track.clone();
+            points[0] = moved_east(points[0], 2000.0);
+
+            let mut report = CleaningReport::default();
+            let kept = drop_spikes(&points, &mut report);
+            assert_eq!(report.spikes, 1, "{dt_s:?}");
+            assert_eq!(lats(&kept), lats(&track[1..]), "{dt_s:?}");
+        }
+    }
+
+    #[test]
+    fn keeps_a_real_jump_after_a_signal_loss() {
+        // Half an hour without signal, then the track goes on 3 km further
+        let mut points = walk(10, 20.0, Some(10));
+        for point in &mut points[5..] {
+            *point = moved_east(*point, 3000.0);
+        }
+        let mut report = CleaningReport::default();
+        assert_eq!(
+            drop_spikes(&points, &mut report).len(),
+            10 - MAX_CONSECUTIVE_REJECTS
+        );
+        assert_eq!(report.spikes, MAX_CONSECUTIVE_REJECTS);
+    }
+
+    #[test]
+    fn collapses_a_one_minute_stop() {
+        let before = walk(10, 10.0, Some(5));
+        let stop_at = before[9];
+        // 60 s of jitter within 5 m, one point a second
+        let stop: Vec<TrackPoint> = (1..=60)
+            .map(|i| {
+                let mut point = moved_east(stop_at, [3.0, -4.0, 1.0, -2.0][i % 4]);
+                point.time = Some(stop_at.time.unwrap() + Duration::seconds(i as i64));
+                point
+            })
+            .collect();
+        let after: Vec<TrackPoint> = walk(10, 10.0, Some(5))
+            .into_iter()
+            .map(|mut p| {
+                p.lat += (90.0 + 20.0) / M_PER_DEG;
+                p.time = Some(p.time.unwrap() + Duration::seconds(120));
+                p
+            })
+            .collect();
+        let points: Vec<TrackPoint> = [before, stop, after].concat();
+
+        let mut report = CleaningReport::default();
+        let collapsed = collapse_stationary(&points, &mut report);
+        assert_eq!(report.stationary, 60);
+        assert_eq!(collapsed.len(), 9 + 1 + 10);
+        // The stop keeps its start time and sits at the jitter's centre
+        let stop = collapsed[9];
+        assert_eq!(stop.time, stop_at.time);
+        assert!(haversine_m(stop.lat, stop.lon, stop_at.lat, stop_at.lon) < 1.0);
+    }
+
+    #[test]
+    fn keeps_a_slow_climb() {
+        // 0.3 m/s for five minutes: within 15 m for 50 s at a time, but never stopped
+        let points = walk(100, 3.0, Some(10));
+        let mut report = CleaningReport::default();
+        let kept = collapse_stationary(&drop_spikes(&points, &mut report), &mut report);
+        assert_eq!((report.spikes, report.stationary), (0, 0));
+        assert_eq!(kept.len(), 100);
+    }
+
+    #[test]
+    fn smooths_zigzags_toward_the_line() {
+        let track = walk(50, 5.0, Some(1));
+        let mut points: Vec<TrackPoint> = track
+            .iter()
+            .enumerate()
+            .map(|(i, &p)| moved_east(p, if i % 2 == 0 { 4.0 } else { -4.0 }))
+            .collect();
+        kalman_smooth(&mut points);
+
+        let offset_m =
+            |p: &TrackPoint, on_line: &TrackPoint| haversine_m(p.lat, p.lon, p.lat, on_line.lon);
+        let worst = points[10..]
+            .iter()
+            .zip(&track[10..])
+            .map(|(p, line)| offset_m(p, line))
+            .fold(0.0, f64::max);
+        assert!(worst < 2.0, "{worst}");
+        assert_eq!(points.len(), 50);
+        assert_eq!(points[0].lat, track[0].lat);
+    }
+}
@@ -1,11 +1,99 @@
-/// Bounding box for the Synclinal de Saou
-pub const BBOX_SOUTH: f64 = 44.6178;
-pub const BBOX_NORTH: f64 = 44.68416;
-pub const BBOX_WEST: f64 = 5.03539;
-pub const BBOX_EAST: f64 = 5.21463;
+/// Regions (bounds, title, zoom...) are configured here, relative to the working directory.
+pub const REGIONS_PATH: &str = "regions.toml";
 
+/// Tile zoom level for regions that don't set one.
 pub const DEFAULT_ZOOM: u32 = 15;
 pub const TILE_SIZE: u32 = 256;
 
-pub const OSM_CACHE_PATH: &str = "data/osm_trails.json";
+/// First day synced when no `--since` is given and there's no previous sync.
+pub const DEFAULT_SINCE: &str = "2026-01-01";
+
+/// Sport types (Garmin activity type keys) that are synced and count toward coverage.
+/// Empty means every sport; a trailing `*` matches a prefix. Names from other sources
+/// are normalized first (Strava "Trail Run" → "trail_running").
+pub const SPORT_INCLUDE: &[&str] = &[
+    "running",
+    "trail_running",
+    "hiking",
+    "walking",
+    "mountaineering",
+    "snow_shoe",
+];
+/// Sport types never counted, even if included.
+pub const SPORT_EXCLUDE: &[&str] = &["treadmill_*", "indoor_*", "virtual_*"];
+
+/// Default trail definition, used unless `regions.toml` sets `[trails]`. Ways matching
+/// any include rule and no exclude rule are trails; each rule is a list of tags that must
+/// all have one of the listed values (`*` for any value).
+pub const TRAIL_INCLUDE: &[&[(&str, &[&str])]] = &[&[(
+    "highway",
+    &[
+        "path",
+        "track",
+        "footway",
+        "bridleway",
+        "steps",
+        "cycleway",
+        "via_ferrata",
+    ],
+)]];
+pub const TRAIL_EXCLUDE: &[&[(&str, &[&str])]] = &[
+    &[("access", &["no", "private"])],
+    &[("foot", &["no", "private"])],
+    &[("abandoned", &["yes"])],
+];
+
+/// Segments shorter than this are dropped if they hang off the network (a dead end or a
+/// boundary cut at one end), else contracted into the junction they lead to
+/// (`min_segment_m` in `regions.toml` overrides it).
+pub const MIN_SEGMENT_M: f64 = 10.0;
+/// Whether segments meeting at a node with no other trail are only merged when their
+/// tags match, so no segment loses its tags or way id to the one it is joined to
+/// (`merge_same_tags` in `regions.toml` overrides it).
+pub const MERGE_SAME_TAGS: bool = true;
+
+/// OSM tags kept on trails and segments, and exported with each segment.
+pub const KEPT_TAGS: &[&str] = &[
+    "highway",
+    "sac_scale",
+    "trail_visibility",
+    "surface",
+    "name",
+    "ref",
+];
+
+/// Activity metadata index, stored inside the activities directory.
+pub const METADATA_FILENAME: &str = "metadata.json";
+
+pub const DATA_DIR: &str = "data";
+/// Cached Overpass response, inside each region's cache directory, as
+/// `<prefix>-<filter key>.json` so each trail filter gets its own cache.
+pub const OSM_CACHE_PREFIX: &str = "osm_trails";
+/// Overpass mirrors, tried in order (`overpass_urls` in `regions.toml` or a comma-separated
+/// `OVERPASS_URL` overrides them, e.g. with a local instance).
+pub const OVERPASS_URLS: &[&str] = &[
+    "https://overpass-api.de/api/interpreter",
+    "https://overpass.kumi.systems/api/interpreter",
+    "https://overpass.private.coffee/api/interpreter",
+];
+/// Cached Overpass responses older than this are fetched again (`osm_cache_days` in
+/// `regions.toml` overrides it).
+pub const OSM_CACHE_MAX_AGE_DAYS: u64 = 30;
+/// Ways added, removed or changed by the last cache refresh, in each region's cache dir.
+pub const OSM_CHANGES_FILENAME: &str = "osm_changes.json";
+/// Boundary of a region defined by an OSM relation, cached in its cache directory as
+/// `<prefix>-<relation id>.geojson` so changing the relation fetches the new one.
+pub const BOUNDARY_CACHE_PREFIX: &str = "boundary";
 pub const TILE_CACHE_DIR: &str = "data/tiles";
+pub const SYNC_STATE_PATH: &str = "data/garmin_sync.json";
+/// Covered/total km per region from the last `update --all-regions`.
+pub const COVERAGE_HISTORY_PATH: &str = "data/coverage_history.json";
+
+/// Nominatim-compatible geocoder used by `region add` (`GEOCODER_URL` overrides it).
+pub const GEOCODER_URL: &str = "https://nominatim.openstreetmap.org";
+/// `region add` writes the found boundary polygon here, as `<id>.geojson`.
+pub const BOUNDARIES_DIR: &str = "boundaries";
+/// `region add` suggests the highest zoom whose stitched map stays within this many
+/// pixels on its longer side, within `SUGGESTED_ZOOM_RANGE`.
+pub const SUGGESTED_MAX_PX: f64 = 5000.0;
+pub const SUGGESTED_ZOOM_RANGE: std::ops::RangeInclusive<u32> = 10..=17;
@@ -3,26 +3,33 @@ use serde_json::{Value, json};
 use std::fs;
 use std::path::Path;
 
+use crate::boundary::{self, Area};
 use crate::grid::{GridConfig, GridResult};
 use crate::matching::{COVERED_THRESHOLD, SegmentCoverage};
 use crate::osm::Segment;
+use crate::region::Region;
 
 pub fn export_json(
     segments: &[Segment],
     coverage: &[SegmentCoverage],
     grid: &GridResult,
+    region: &Region,
+    area: &Area,
     output: &str,
 ) -> Result<()> {
     let segment_features = build_segment_features(segments, coverage, &grid.segment_cells);
     let cell_features = build_cell_features(grid);
 
     let data = json!({
+        "region": region.id,
+        "title": region.title,
         "bbox": [
-            crate::config::BBOX_WEST,
-            crate::config::BBOX_SOUTH,
-            crate::config::BBOX_EAST,
-            crate::config::BBOX_NORTH,
+            area.bounds.west,
+            area.bounds.south,
+            area.bounds.east,
+            area.bounds.north,
         ],
+        "boundary": area.boundary.as_ref().map(boundary::to_geojson),
         "grid": {
             "cell_size_m": grid.config.cell_size_m,
             "origin": [grid.config.origin_lon, grid.config.origin_lat],
@@ -85,19 +92,28 @@ fn build_segment_features(
             let cov = &coverage[i];
             let coords: Vec<Value> = seg.geometry.0.iter().map(|c| json!([c.x, c.y])).collect();
 
+            let mut properties = json!({
+                "id": seg.id,
+                "length_m": (cov.length_m * 10.0).round() / 10.0,
+                "coverage_pct": (cov.coverage_pct * 100.0).round() / 100.0,
+                "covered": cov.coverage_pct >= COVERED_THRESHOLD,
+                "cells": segment_cells[i],
+                "way_id": seg.way_id,
+                "start_node": seg.start_node,
+                "end_node": seg.end_node,
+            });
+            // OSM tags as top-level properties, so the web UI can style and filter on them
+            for (key, value) in &seg.tags {
+                properties[key] = json!(value);
+            }
+
             json!({
                 "type": "Feature",
                 "geometry": {
                     "type": "LineString",
                     "coordinates": coords,
                 },
-                "properties": {
-                    "id": i,
-                    "length_m": (cov.length_m * 10.0).round() / 10.0,
-                    "coverage_pct": (cov.coverage_pct * 100.0).round() / 100.0,
-                    "covered": cov.coverage_pct >= COVERED_THRESHOLD,
-                    "cells": segment_cells[i],
-                },
+                "properties": properties,
             })
         })
         .collect()
@@ -1,207 +1,772 @@
+mod client;
+
 use anyhow::{Context, Result};
-use serde::Deserialize;
+use futures::stream::{self, StreamExt};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
 use std::fs;
 use std::path::Path;
-use std::process::Command;
+use std::time::Duration;
 
+use crate::boundary::{self, Area};
 use crate::config::*;
+use crate::fs_util::write_atomic;
+use crate::hash::fnv1a;
+use crate::metadata::{ActivityMeta, MetadataStore, Source};
+use crate::region::Bounds;
+use crate::sport;
+
+pub use client::{ActivitySummary, DownloadFormat, GarminApi, GarminClient};
+use client::{GeoPolyline, PolylinePoint};
+
+const PAGE_SIZE: usize = 100;
+/// Activities fetched at once. Garmin rate-limits aggressive clients, so keep this small.
+const DOWNLOAD_CONCURRENCY: usize = 4;
+/// Attempts per request before giving up on an activity, doubling the delay each time.
+const MAX_ATTEMPTS: u32 = 4;
+const RETRY_BASE_DELAY: Duration = Duration::from_secs(1);
+
+/// Sync progress for each set of areas synced (see `areas_key`), so syncing another region,
+/// or a region whose boundary changed, doesn't inherit a cursor or "too far" verdicts made
+/// for other areas.
+#[derive(Default, Serialize, Deserialize)]
+struct SyncStates {
+    #[serde(default)]
+    areas: BTreeMap<String, SyncState>,
+}
 
-const COORD_BUFFER: f64 = 0.15; // ~15km buffer around bbox for start coordinate check
+impl SyncStates {
+    /// The state for `key`. A new one starts without a cursor, but with the rejections that
+    /// don't depend on the area (indoor, no GPS) already made for other areas.
+    fn take(&mut self, key: &str) -> SyncState {
+        if let Some(state) = self.areas.remove(key) {
+            return state;
+        }
+        let rejected = self
+            .areas
+            .values()
+            .flat_map(|state| &state.rejected)
+            .filter(|(_, rejected)| !matches!(rejected.reason, RejectReason::TooFar))
+            .map(|(id, rejected)| (*id, rejected.clone()))
+            .collect();
+        SyncState {
+            last_synced: None,
+            rejected,
+        }
+    }
+}
+
+/// Persisted between runs so routine syncs only list activities newer than the last one.
+#[derive(Default, Serialize, Deserialize)]
+struct SyncState {
+    /// `startTimeLocal` of the newest activity seen by a previous sync.
+    last_synced: Option<String>,
+    /// Activities already checked and rejected, so later syncs don't query them again.
+    #[serde(default)]
+    rejected: BTreeMap<u64, Rejected>,
+}
 
-#[derive(Deserialize)]
-struct ActivityDetails {
-    #[serde(rename = "activityName")]
-    activity_name: Option<String>,
-    #[serde(rename = "summaryDTO")]
-    summary: Option<SummaryDTO>,
+#[derive(Clone, Serialize, Deserialize)]
+struct Rejected {
+    date: String,
+    reason: RejectReason,
 }
 
-#[derive(Deserialize)]
-struct SummaryDTO {
-    #[serde(rename = "startLatitude")]
-    start_latitude: Option<f64>,
-    #[serde(rename = "startLongitude")]
-    start_longitude: Option<f64>,
-    distance: Option<f64>,
+/// Why an activity wasn't downloaded, after looking at its details.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum RejectReason {
+    /// Has a distance but no track, e.g. a treadmill run logged as a run.
+    Indoor,
+    NoGps,
+    TooFar,
 }
 
-pub fn sync(activities_dir: &str, since: &str) -> Result<()> {
+impl RejectReason {
+    fn describe(self) -> &'static str {
+        match self {
+            Self::Indoor => "indoor",
+            Self::NoGps => "no GPS coordinates",
+            Self::TooFar => "too far",
+        }
+    }
+}
+
+/// Outcome of fetching one activity.
+enum Fetched {
+    /// Downloaded to this file name.
+    File(String),
+    Rejected(RejectReason),
+}
+
+/// Sync activities between `since` and `until` (inclusive dates, YYYY-MM-DD).
+///
+/// Without an explicit `since`, only activities newer than the previous sync are listed
+/// (or everything since `DEFAULT_SINCE` on the first run). Activities rejected by a previous
+/// sync are skipped without querying them again, unless `recheck` is set: then the list
+/// goes back to the oldest rejected activity and those are fetched again.
+pub async fn sync(
+    api: &impl GarminApi,
+    activities_dir: &str,
+    areas: &[Area],
+    since: Option<&str>,
+    until: Option<&str>,
+    format: DownloadFormat,
+    recheck: bool,
+) -> Result<()> {
     fs::create_dir_all(activities_dir)?;
+    let mut metadata = MetadataStore::load(activities_dir)?;
+
+    let key = areas_key(areas);
+    let mut states = load_sync_states()?;
+    let mut state = states.take(&key);
+    // Rejected activities are older than the cursor, so re-checking them means listing back
+    // to the oldest one. The other activities before the cursor are skipped again.
+    let mut recheck_ids = HashSet::new();
+    let mut oldest_rejected: Option<String> = None;
+    if recheck {
+        for (id, rejected) in std::mem::take(&mut state.rejected) {
+            if metadata.contains(&id.to_string()) {
+                continue;
+            }
+            if oldest_rejected.as_ref().is_none_or(|d| rejected.date < *d) {
+                oldest_rejected = Some(rejected.date);
+            }
+            recheck_ids.insert(id);
+        }
+        eprintln!(
+            "Re-checking {} previously rejected activities",
+            recheck_ids.len()
+        );
+    }
+    let cursor = state.last_synced.clone();
+    let after = match (since, &cursor, &oldest_rejected) {
+        (Some(since), ..) => ListFrom::Date(since),
+        (None, Some(_), Some(oldest)) => ListFrom::Date(oldest),
+        (None, Some(cursor), None) => ListFrom::After(cursor),
+        (None, None, _) => ListFrom::Date(DEFAULT_SINCE),
+    };
+    let only_rechecks_before = match (since, &oldest_rejected) {
+        (None, Some(_)) => cursor.as_deref(),
+        _ => None,
+    };
 
-    let ids = list_activity_ids(since)?;
-    eprintln!("Found {} activities since {since}", ids.len());
+    let activities = list_activities(api, &after, until).await?;
+    match after {
+        ListFrom::Date(date) => eprintln!("Found {} activities since {date}", activities.len()),
+        ListFrom::After(cursor) => eprintln!(
+            "Found {} activities since last sync ({cursor})",
+            activities.len()
+        ),
+    }
+    // A bounded `--until` run doesn't reach the present, so it mustn't move the cursor
+    let newest = match until {
+        Some(_) => None,
+        None => activities.first().map(|a| a.start_time_local.clone()),
+    };
 
-    let mut downloaded = 0;
-    for (i, (id, date, activity_type)) in ids.iter().enumerate() {
-        // Skip activities without GPS (strength, indoor)
-        if is_indoor_activity(activity_type) {
+    // Cheap checks first; only activities that need network requests are fetched below
+    let total = activities.len();
+    let mut pending = Vec::new();
+    for (i, activity) in activities.iter().enumerate() {
+        let id = activity.id;
+        let activity_type = activity.type_key();
+
+        if only_rechecks_before.is_some_and(|c| activity.start_time_local.as_str() <= c)
+            && !recheck_ids.contains(&id)
+        {
+            continue;
+        }
+
+        // Skip sports that don't count (and indoor activities without GPS)
+        if !sport::is_allowed(Some(activity_type).filter(|t| !t.is_empty())) {
             eprintln!(
-                "[{}/{}] Skipping {} ({}) — indoor/no GPS",
+                "[{}/{}] Skipping {} ({}) — sport type excluded",
                 i + 1,
-                ids.len(),
+                total,
                 id,
                 activity_type
             );
             continue;
         }
 
-        // Check if already downloaded
-        let gpx_path = format!("{activities_dir}/{id}.gpx");
-        if Path::new(&gpx_path).exists() {
-            eprintln!("[{}/{}] Already have {id}.gpx", i + 1, ids.len());
+        // Check if already downloaded, in any format
+        if let Some(existing) = existing_file(activities_dir, id) {
+            eprintln!("[{}/{}] Already have {existing}", i + 1, total);
+            // Backfill files downloaded before metadata was recorded
+            if !metadata.contains(&id.to_string()) {
+                metadata.insert(activity_meta(activity, existing));
+                metadata.checkpoint()?;
+            }
             continue;
         }
 
-        // Get details to check location
-        let info = match get_activity_location(id)? {
-            Some(info) => info,
-            None => {
-                eprintln!(
-                    "[{}/{}] Skipping {id} ({date}) — no GPS coordinates",
-                    i + 1,
-                    ids.len()
-                );
-                continue;
-            }
-        };
-
-        if !is_near_bbox(info.start_lat, info.start_lon) {
+        if let Some(rejected) = state.rejected.get(&id) {
             eprintln!(
-                "[{}/{}] Skipping {} — {} too far ({:.3}, {:.3})",
+                "[{}/{}] Skipping {id} ({}) — {} (checked before)",
                 i + 1,
-                ids.len(),
-                id,
-                info.name,
-                info.start_lat,
-                info.start_lon
+                total,
+                rejected.date,
+                rejected.reason.describe()
             );
             continue;
         }
 
-        eprintln!(
-            "[{}/{}] Downloading {id} — {} ({date}, {:.1} km)",
-            i + 1,
-            ids.len(),
-            info.name,
-            info.distance_km
-        );
-        download_gpx(id, &gpx_path)?;
-        downloaded += 1;
+        pending.push((i + 1, activity));
     }
 
-    eprintln!("Downloaded {downloaded} new GPX files to {activities_dir}");
-    Ok(())
-}
+    let mut results = stream::iter(pending)
+        .map(|(n, activity)| async move {
+            let progress = format!("[{n}/{total}]");
+            let result =
+                fetch_activity(api, activities_dir, areas, activity, format, &progress).await;
+            (activity, result)
+        })
+        .buffer_unordered(DOWNLOAD_CONCURRENCY);
 
-struct LocationInfo {
-    name: String,
-    start_lat: f64,
-    start_lon: f64,
-    distance_km: f64,
-}
+    let mut downloaded = 0;
+    let mut failures = Vec::new();
+    while let Some((activity, result)) = results.next().await {
+        match result {
+            Ok(Fetched::File(file)) => {
+                metadata.insert(activity_meta(activity, file));
+                metadata.checkpoint()?;
+                downloaded += 1;
+            }
+            Ok(Fetched::Rejected(reason)) => {
+                let rejected = Rejected {
+                    date: activity.date().to_string(),
+                    reason,
+                };
+                state.rejected.insert(activity.id, rejected);
+            }
+            Err(e) => failures.push((activity, e)),
+        }
+    }
+    metadata.save()?;
 
-fn list_activity_ids(since: &str) -> Result<Vec<(String, String, String)>> {
-    // garmin-cli list doesn't support date filtering or JSON output,
-    // so we fetch a large batch and filter by date ourselves
-    let output = Command::new("garmin")
-        .args(["activities", "list", "-l", "200"])
-        .output()
-        .context("Failed to run 'garmin activities list'")?;
+    eprintln!(
+        "Downloaded {downloaded} new {} files to {activities_dir}",
+        format.extension().to_uppercase()
+    );
 
-    if !output.status.success() {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        anyhow::bail!("garmin activities list failed: {stderr}");
+    if !failures.is_empty() {
+        eprintln!("{} activities failed:", failures.len());
+        for (activity, e) in &failures {
+            eprintln!("  {} ({}) — {e:#}", activity.id, activity.date());
+        }
+        // Keep the cursor where it was so the next sync lists (and retries) them again
+        eprintln!("Run sync again to retry them");
+    } else if let Some(newest) = newest
+        && state.last_synced.as_ref().is_none_or(|last| newest > *last)
+    {
+        state.last_synced = Some(newest);
     }
+    states.areas.insert(key, state);
+    save_sync_states(&states)
+}
 
-    let stdout = String::from_utf8_lossy(&output.stdout);
-    let mut activities = Vec::new();
-
-    for line in stdout.lines() {
-        let line = line.trim();
-        // Parse table rows: "21887868116  2026-02-16 trail_running     15.61 km      2:08:53        -"
-        let parts: Vec<&str> = line.split_whitespace().collect();
-        if parts.len() < 3 {
-            continue;
+/// Check whether an activity crosses the bbox and download it if so.
+///
+/// The details polyline decides when there is one; otherwise the track is downloaded and
+/// tested, and only kept if `load_activities` would accept it.
+async fn fetch_activity(
+    api: &impl GarminApi,
+    activities_dir: &str,
+    areas: &[Area],
+    activity: &ActivitySummary,
+    format: DownloadFormat,
+    progress: &str,
+) -> Result<Fetched> {
+    let id = activity.id;
+    let date = activity.date();
+    let name = activity.name.as_deref().unwrap_or("(unnamed)");
+    let distance_km = activity.distance.unwrap_or(0.0) / 1000.0;
+
+    let details = with_retry(|| api.activity_details(id)).await?;
+    match details
+        .polyline
+        .as_ref()
+        .map(|p| polyline_in_areas(p, areas))
+    {
+        Some(InBbox::No) => {
+            eprintln!("{progress} Skipping {id} — {name} too far ({date})");
+            return Ok(Fetched::Rejected(RejectReason::TooFar));
         }
-        // First field should be a numeric ID
-        if !parts[0].chars().all(|c| c.is_ascii_digit()) {
-            continue;
+        Some(InBbox::Yes) => {
+            eprintln!("{progress} Downloading {id} — {name} ({date}, {distance_km:.1} km)")
         }
-        let id = parts[0].to_string();
-        let date = parts[1].to_string();
-        let activity_type = parts[2].to_string();
+        Some(InBbox::Unknown) | None => eprintln!(
+            "{progress} Downloading {id} — {name} ({date}, {distance_km:.1} km) to check its track"
+        ),
+    }
 
-        if date.as_str() < since {
-            break; // Activities are sorted newest first
+    let bytes = with_retry(|| api.download(id, format)).await?;
+
+    // The file is only kept if it has a track crossing the bbox
+    match format.track_format().read(bytes.as_slice()) {
+        Ok(raw) if raw.tracks.iter().all(|points| points.len() < 2) => {
+            let reason = if distance_km > 0.0 {
+                RejectReason::Indoor
+            } else {
+                RejectReason::NoGps
+            };
+            eprintln!("{progress} Skipping {id} ({date}) — {}", reason.describe());
+            return Ok(Fetched::Rejected(reason));
         }
+        Ok(raw) if !areas.iter().any(|area| raw.touches_area(area)) => {
+            eprintln!("{progress} Skipping {id} — {name} too far ({date})");
+            return Ok(Fetched::Rejected(RejectReason::TooFar));
+        }
+        Ok(_) => {}
+        // Keep it anyway; loading will report the problem
+        Err(e) => eprintln!("Warning: failed to parse activity {id}: {e:#}"),
+    }
+
+    let file = format!("{id}.{}", format.extension());
+    write_atomic(&Path::new(activities_dir).join(&file), &bytes)?;
+    Ok(Fetched::File(file))
+}
 
-        activities.push((id, date, activity_type));
+/// The best answer over all areas: in one of them, else unknown for one, else in none.
+fn polyline_in_areas(polyline: &GeoPolyline, areas: &[Area]) -> InBbox {
+    let answers: Vec<InBbox> = areas
+        .iter()
+        .map(|area| polyline_in_bbox(polyline, &area.bounds))
+        .collect();
+    if answers.iter().any(|a| matches!(a, InBbox::Yes)) {
+        InBbox::Yes
+    } else if answers.iter().any(|a| matches!(a, InBbox::Unknown)) {
+        InBbox::Unknown
+    } else {
+        InBbox::No
     }
+}
 
-    Ok(activities)
+enum InBbox {
+    Yes,
+    No,
+    /// No usable polyline, so the track itself has to be checked.
+    Unknown,
 }
 
-fn get_activity_location(id: &str) -> Result<Option<LocationInfo>> {
-    let output = Command::new("garmin")
-        .args(["activities", "get", id, "-f", "json"])
-        .output()
-        .with_context(|| format!("Failed to run 'garmin activities get {id}'"))?;
+/// Whether the simplified track crosses the bbox. Segments are tested rather than points,
+/// since simplification can drop the few points of a short pass through the area.
+fn polyline_in_bbox(polyline: &GeoPolyline, bounds: &Bounds) -> InBbox {
+    if let (Some(min_lat), Some(max_lat), Some(min_lon), Some(max_lon)) = (
+        polyline.min_lat,
+        polyline.max_lat,
+        polyline.min_lon,
+        polyline.max_lon,
+    ) && (max_lat < bounds.south
+        || min_lat > bounds.north
+        || max_lon < bounds.west
+        || min_lon > bounds.east)
+    {
+        return InBbox::No;
+    }
 
-    if !output.status.success() {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        anyhow::bail!("garmin activities get {id} failed: {stderr}");
+    let points = &polyline.polyline;
+    if points.len() < 2 {
+        return InBbox::Unknown;
     }
+    if points
+        .windows(2)
+        .any(|w| segment_crosses_bbox(&w[0], &w[1], bounds))
+    {
+        InBbox::Yes
+    } else {
+        InBbox::No
+    }
+}
 
-    let details: ActivityDetails = serde_json::from_slice(&output.stdout)
-        .with_context(|| format!("Failed to parse activity {id} JSON"))?;
+/// Liang–Barsky: clip the segment against the bbox and see if anything is left.
+fn segment_crosses_bbox(a: &PolylinePoint, b: &PolylinePoint, bounds: &Bounds) -> bool {
+    let (dx, dy) = (b.lon - a.lon, b.lat - a.lat);
+    let (mut t0, mut t1) = (0.0_f64, 1.0_f64);
+    for (p, q) in [
+        (-dx, a.lon - bounds.west),
+        (dx, bounds.east - a.lon),
+        (-dy, a.lat - bounds.south),
+        (dy, bounds.north - a.lat),
+    ] {
+        if p == 0.0 {
+            if q < 0.0 {
+                return false;
+            }
+        } else {
+            let t = q / p;
+            if p < 0.0 {
+                t0 = t0.max(t);
+            } else {
+                t1 = t1.min(t);
+            }
+        }
+    }
+    t0 <= t1
+}
 
-    let summary = match details.summary {
-        Some(s) => s,
-        None => return Ok(None),
-    };
+/// Run a request, retrying transient failures with exponential backoff.
+async fn with_retry<T, F, Fut>(mut request: F) -> Result<T>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T>>,
+{
+    let mut delay = RETRY_BASE_DELAY;
+    let mut attempt = 1;
+    loop {
+        match request().await {
+            Ok(value) => return Ok(value),
+            Err(e) if attempt < MAX_ATTEMPTS && is_transient(&e) => {
+                eprintln!(
+                    "Warning: {e:#} (attempt {attempt}/{MAX_ATTEMPTS}), retrying in {}s",
+                    delay.as_secs()
+                );
+                tokio::time::sleep(delay).await;
+                delay *= 2;
+                attempt += 1;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
 
-    let (lat, lon) = match (summary.start_latitude, summary.start_longitude) {
-        (Some(lat), Some(lon)) => (lat, lon),
-        _ => return Ok(None),
+/// Server errors, rate limiting and network timeouts may go away by asking again. Client
+/// errors (bad id, expired token) and bad responses (unparsable JSON, an archive without
+/// a FIT file) won't.
+fn is_transient(error: &anyhow::Error) -> bool {
+    let Some(error) = error.downcast_ref::<reqwest::Error>() else {
+        return false;
     };
+    match error.status() {
+        Some(status) => {
+            status.is_server_error()
+                || status == StatusCode::REQUEST_TIMEOUT
+                || status == StatusCode::TOO_MANY_REQUESTS
+        }
+        None => error.is_timeout() || error.is_connect(),
+    }
+}
 
-    let name = details.activity_name.unwrap_or_default();
-    let distance_km = summary.distance.unwrap_or(0.0) / 1000.0;
+enum ListFrom<'a> {
+    /// Everything starting on or after this date.
+    Date(&'a str),
+    /// Everything starting strictly after this `startTimeLocal`.
+    After(&'a str),
+}
 
-    Ok(Some(LocationInfo {
-        name,
-        start_lat: lat,
-        start_lon: lon,
-        distance_km,
-    }))
+/// Page backwards through the activity list until crossing the lower bound.
+async fn list_activities(
+    api: &impl GarminApi,
+    from: &ListFrom<'_>,
+    until: Option<&str>,
+) -> Result<Vec<ActivitySummary>> {
+    let mut activities = Vec::new();
+    let mut start = 0;
+
+    loop {
+        let page = api.list_activities(start, PAGE_SIZE).await?;
+        let page_len = page.len();
+
+        for activity in page {
+            let reached_end = match from {
+                ListFrom::Date(date) => activity.date() < *date,
+                ListFrom::After(cursor) => activity.start_time_local.as_str() <= *cursor,
+            };
+            // Activities are sorted newest first
+            if reached_end {
+                return Ok(activities);
+            }
+            if until.is_some_and(|until| activity.date() > until) {
+                continue;
+            }
+            activities.push(activity);
+        }
+
+        if page_len < PAGE_SIZE {
+            return Ok(activities);
+        }
+        start += page_len;
+    }
 }
 
-fn download_gpx(id: &str, output_path: &str) -> Result<()> {
-    let output = Command::new("garmin")
-        .args(["activities", "download", id, "-t", "gpx", "-o", output_path])
-        .output()
-        .with_context(|| format!("Failed to download activity {id}"))?;
+/// A key for a set of areas: a hash of their bounds and boundaries, in any order.
+fn areas_key(areas: &[Area]) -> String {
+    let mut parts: Vec<String> = areas
+        .iter()
+        .map(|area| {
+            let boundary = area.boundary.as_ref().map(boundary::to_geojson);
+            serde_json::json!([area.bounds, boundary]).to_string()
+        })
+        .collect();
+    parts.sort();
+    format!("{:016x}", fnv1a(parts.join("\n").as_bytes()))
+}
 
-    if !output.status.success() {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        anyhow::bail!("garmin activities download {id} failed: {stderr}");
+fn load_sync_states() -> Result<SyncStates> {
+    let path = Path::new(SYNC_STATE_PATH);
+    if !path.exists() {
+        return Ok(SyncStates::default());
     }
+    let json = fs::read_to_string(path)?;
+    serde_json::from_str(&json).with_context(|| format!("Failed to parse {SYNC_STATE_PATH}"))
+}
 
+fn save_sync_states(state: &SyncStates) -> Result<()> {
+    let path = Path::new(SYNC_STATE_PATH);
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, serde_json::to_string_pretty(state)?)?;
     Ok(())
 }
 
-fn is_near_bbox(lat: f64, lon: f64) -> bool {
-    (BBOX_SOUTH - COORD_BUFFER..=BBOX_NORTH + COORD_BUFFER).contains(&lat)
-        && (BBOX_WEST - COORD_BUFFER..=BBOX_EAST + COORD_BUFFER).contains(&lon)
+fn activity_meta(activity: &ActivitySummary, file: String) -> ActivityMeta {
+    ActivityMeta {
+        id: activity.id.to_string(),
+        name: activity.name.clone(),
+        date: Some(activity.date().to_string()),
+        sport: Some(activity.type_key().to_string()).filter(|t| !t.is_empty()),
+        distance_m: activity.distance,
+        duration_s: activity.duration,
+        source: Source::Garmin,
+        file,
+    }
+}
+
+fn existing_file(activities_dir: &str, id: u64) -> Option<String> {
+    ["gpx", "fit", "fit.gz"]
+        .iter()
+        .map(|ext| format!("{id}.{ext}"))
+        .find(|name| Path::new(activities_dir).join(name).exists())
 }
 
-fn is_indoor_activity(activity_type: &str) -> bool {
-    // Activity types may be truncated with "..." in table output
-    let t = activity_type.trim_end_matches("...");
-    t.starts_with("strength")
-        || t.starts_with("indoor")
-        || t.starts_with("treadmill")
-        || t.starts_with("yoga")
-        || t.starts_with("breathwork")
-        || t == "0.00" // fallback: 0 distance sometimes appears as type
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_server::{MockServer, fixture};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Serves the two recorded list pages (100 + 20 activities, newest first).
+    async fn paged_server() -> (MockServer, GarminClient) {
+        let server = MockServer::start(|path| {
+            let page = match path
+                .split("start=")
+                .nth(1)
+                .and_then(|q| q.split('&').next())
+            {
+                Some("0") => "garmin/activities_page_0.json",
+                Some("100") => "garmin/activities_page_100.json",
+                _ => "garmin/activities_empty.json",
+            };
+            (200, fixture(page))
+        })
+        .await;
+        let client = GarminClient::new(reqwest::Client::new(), &server.url, "token");
+        (server, client)
+    }
+
+    #[tokio::test]
+    async fn pages_until_the_list_ends() {
+        let (server, client) = paged_server().await;
+
+        let activities = list_activities(&client, &ListFrom::Date("2026-01-01"), None)
+            .await
+            .unwrap();
+
+        assert_eq!(activities.len(), 120);
+        assert_eq!(server.paths().len(), 2);
+        assert!(server.paths()[1].contains("start=100&limit=100"));
+    }
+
+    #[tokio::test]
+    async fn stops_paging_at_the_lower_bound() {
+        let (server, client) = paged_server().await;
+
+        let activities = list_activities(&client, &ListFrom::Date("2026-06-21"), None)
+            .await
+            .unwrap();
+        assert_eq!(activities.len(), 20);
+        assert!(activities.iter().all(|a| a.date() >= "2026-06-21"));
+
+        let cursor = "2026-05-21 10:12:41";
+        let activities = list_activities(&client, &ListFrom::After(cursor), Some("2026-06-29"))
+            .await
+            .unwrap();
+        assert_eq!(activities.len(), 79);
+        assert!(
+            activities
+                .iter()
+                .all(|a| a.start_time_local.as_str() > cursor && a.date() <= "2026-06-29")
+        );
+        // One request for the first case, one for the second: neither reaches page two
+        assert_eq!(server.paths().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn retries_rate_limited_requests() {
+        let hits = AtomicUsize::new(0);
+        let server = MockServer::start(move |_| match hits.fetch_add(1, Ordering::SeqCst) {
+            0 => (429, b"Too Many Requests".to_vec()),
+            _ => (200, fixture("garmin/details_in_area.json")),
+        })
+        .await;
+        let client = GarminClient::new(reqwest::Client::new(), &server.url, "token");
+
+        let details = with_retry(|| client.activity_details(21000000120))
+            .await
+            .unwrap();
+
+        assert!(details.polyline.is_some());
+        assert_eq!(server.paths().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn does_not_retry_bad_responses() {
+        let server = MockServer::start(|_| (200, b"<html>Maintenance</html>".to_vec())).await;
+        let client = GarminClient::new(reqwest::Client::new(), &server.url, "token");
+
+        assert!(with_retry(|| client.activity_details(1)).await.is_err());
+        assert!(
+            with_retry(|| client.download(1, DownloadFormat::Fit))
+                .await
+                .is_err()
+        );
+        assert_eq!(server.paths().len(), 2);
+    }
+
+    fn area(south: f64, west: f64) -> Area {
+        Area {
+            bounds: Bounds {
+                south,
+                west,
+                north: south + 0.1,
+                east: west + 0.1,
+            },
+            boundary: None,
+        }
+    }
+
+    #[test]
+    fn keys_sync_state_by_area_set() {
+        let saou = || area(44.6, 5.0);
+        let vercors = || area(45.0, 5.5);
+
+        let both = areas_key(&[saou(), vercors()]);
+        assert_eq!(both, areas_key(&[vercors(), saou()]));
+        assert_ne!(both, areas_key(&[saou()]));
+        assert_ne!(areas_key(&[saou()]), areas_key(&[vercors()]));
+        assert_eq!(areas_key(&[saou()]), areas_key(&[saou()]));
+    }
+
+    #[test]
+    fn new_area_set_keeps_only_area_independent_rejections() {
+        let rejected = |reason| Rejected {
+            date: "2026-03-01".to_string(),
+            reason,
+        };
+        let mut states = SyncStates::default();
+        states.areas.insert(
+            "saou".to_string(),
+            SyncState {
+                last_synced: Some("2026-06-30 18:12:41".to_string()),
+                rejected: BTreeMap::from([
+                    (1, rejected(RejectReason::TooFar)),
+                    (2, rejected(RejectReason::Indoor)),
+                    (3, rejected(RejectReason::NoGps)),
+                ]),
+            },
+        );
+
+        let vercors = states.take("vercors");
+        assert!(vercors.last_synced.is_none());
+        assert_eq!(vercors.rejected.keys().collect::<Vec<_>>(), [&2, &3]);
+
+        let saou = states.take("saou");
+        assert!(saou.last_synced.is_some());
+        assert_eq!(saou.rejected.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn gives_up_on_client_errors() {
+        let server = MockServer::start(|_| (404, Vec::new())).await;
+        let client = GarminClient::new(reqwest::Client::new(), &server.url, "token");
+
+        assert!(with_retry(|| client.activity_details(1)).await.is_err());
+        assert_eq!(server.paths().len(), 1);
+    }
+
+    fn polyline(name: &str) -> Option<GeoPolyline> {
+        serde_json::from_slice::<client::ActivityDetails>(&fixture(name))
+            .unwrap()
+            .polyline
+    }
+
+    /// A polyline without its bounding box, as Garmin sometimes sends it.
+    fn bare_polyline(points: &[(f64, f64)]) -> GeoPolyline {
+        GeoPolyline {
+            min_lat: None,
+            max_lat: None,
+            min_lon: None,
+            max_lon: None,
+            polyline: points
+                .iter()
+                .map(|&(lat, lon)| PolylinePoint { lat, lon })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn finds_polylines_in_or_far_from_the_areas() {
+        let saou = area(44.6, 5.1);
+        let in_area = polyline("garmin/details_in_area.json").unwrap();
+        let far = polyline("garmin/details_far.json").unwrap();
+
+        assert!(matches!(
+            polyline_in_bbox(&in_area, &saou.bounds),
+            InBbox::Yes
+        ));
+        assert!(matches!(polyline_in_bbox(&far, &saou.bounds), InBbox::No));
+        assert!(matches!(
+            polyline_in_areas(&far, &[area(45.0, 5.5), saou]),
+            InBbox::No
+        ));
+        assert!(matches!(
+            polyline_in_areas(&in_area, &[area(45.0, 5.5), area(44.6, 5.1)]),
+            InBbox::Yes
+        ));
+    }
+
+    #[test]
+    fn counts_a_track_passing_through() {
+        let saou = area(44.6, 5.1).bounds;
+        // Both ends outside, west and east of the box
+        let through = bare_polyline(&[(44.62, 5.0), (44.68, 5.3)]);
+        assert!(matches!(polyline_in_bbox(&through, &saou), InBbox::Yes));
+        // Both ends outside, cutting past the box's corner
+        let past = bare_polyline(&[(44.65, 5.0), (44.75, 5.12)]);
+        assert!(matches!(polyline_in_bbox(&past, &saou), InBbox::No));
+    }
+
+    #[test]
+    fn leaves_tracks_without_a_polyline_unknown() {
+        assert!(polyline("garmin/details_no_polyline.json").is_none());
+
+        let saou = area(44.6, 5.1);
+        let single_point = bare_polyline(&[(44.65, 5.15)]);
+        assert!(matches!(
+            polyline_in_bbox(&single_point, &saou.bounds),
+            InBbox::Unknown
+        ));
+        let far = polyline("garmin/details_far.json").unwrap();
+        assert!(matches!(
+            polyline_in_areas(&single_point, &[area(45.0, 5.5), saou]),
+            InBbox::Unknown
+        ));
+        assert!(matches!(
+            polyline_in_areas(&far, &[area(45.0, 5.5)]),
+            InBbox::No
+        ));
+    }
 }
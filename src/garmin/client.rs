@@ -0,0 +1,349 @@
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::io::{Cursor, Read};
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::gpx::TrackFormat;
+
+pub const DEFAULT_API_URL: &str = "https://connectapi.garmin.com";
+
+/// Tokens are shared with garmin-cli, so `garmin auth login` is still how you log in.
+const TOKEN_FILENAME: &str = "oauth2_token.json";
+const TOKEN_PROFILE: &str = "default";
+/// Polyline points requested with activity details; plenty to tell whether a track
+/// crosses the area.
+const MAX_POLYLINE_SIZE: usize = 2000;
+
+/// The subset of the Garmin Connect API used by `sync`.
+pub trait GarminApi {
+    /// One page of activities, newest first.
+    async fn list_activities(&self, start: usize, limit: usize) -> Result<Vec<ActivitySummary>>;
+    async fn activity_details(&self, id: u64) -> Result<ActivityDetails>;
+    async fn download(&self, id: u64, format: DownloadFormat) -> Result<Vec<u8>>;
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum DownloadFormat {
+    Gpx,
+    /// The original FIT file, unpacked from the zip Garmin serves it in.
+    Fit,
+}
+
+impl DownloadFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Gpx => "gpx",
+            Self::Fit => "fit",
+        }
+    }
+
+    pub fn track_format(&self) -> TrackFormat {
+        match self {
+            Self::Gpx => TrackFormat::Gpx,
+            Self::Fit => TrackFormat::Fit,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ActivitySummary {
+    #[serde(rename = "activityId")]
+    pub id: u64,
+    #[serde(rename = "activityName")]
+    pub name: Option<String>,
+    /// Local start time, "YYYY-MM-DD HH:MM:SS".
+    #[serde(rename = "startTimeLocal")]
+    pub start_time_local: String,
+    #[serde(rename = "activityType")]
+    pub activity_type: Option<ActivityType>,
+    /// Meters.
+    pub distance: Option<f64>,
+    /// Seconds.
+    pub duration: Option<f64>,
+}
+
+impl ActivitySummary {
+    pub fn date(&self) -> &str {
+        self.start_time_local
+            .split_whitespace()
+            .next()
+            .unwrap_or_default()
+    }
+
+    pub fn type_key(&self) -> &str {
+        self.activity_type
+            .as_ref()
+            .map(|t| t.type_key.as_str())
+            .unwrap_or_default()
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ActivityType {
+    #[serde(rename = "typeKey")]
+    pub type_key: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ActivityDetails {
+    /// Missing for activities without GPS, and sometimes for ones still being processed.
+    #[serde(rename = "geoPolylineDTO")]
+    pub polyline: Option<GeoPolyline>,
+}
+
+/// A simplified version of the recorded track, with its bounding box.
+#[derive(Debug, Deserialize)]
+pub struct GeoPolyline {
+    #[serde(rename = "minLat")]
+    pub min_lat: Option<f64>,
+    #[serde(rename = "maxLat")]
+    pub max_lat: Option<f64>,
+    #[serde(rename = "minLon")]
+    pub min_lon: Option<f64>,
+    #[serde(rename = "maxLon")]
+    pub max_lon: Option<f64>,
+    #[serde(default)]
+    pub polyline: Vec<PolylinePoint>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PolylinePoint {
+    pub lat: f64,
+    pub lon: f64,
+}
+
+#[derive(Deserialize)]
+struct OAuth2Token {
+    access_token: String,
+    #[serde(default)]
+    expires_at: i64,
+}
+
+/// HTTP client for the Garmin Connect API.
+pub struct GarminClient {
+    http: reqwest::Client,
+    base_url: String,
+    authorization: String,
+}
+
+impl GarminClient {
+    pub fn new(http: reqwest::Client, base_url: &str, access_token: &str) -> Self {
+        Self {
+            http,
+            base_url: base_url.trim_end_matches('/').to_string(),
+            authorization: format!("Bearer {access_token}"),
+        }
+    }
+
+    /// Build a client from the garmin-cli token store.
+    ///
+    /// `GARMIN_API_URL` overrides the API base URL (e.g. to point at a local mock server)
+    /// and `GARMIN_TOKEN_DIR` overrides where `oauth2_token.json` is read from.
+    pub fn from_env(http: reqwest::Client) -> Result<Self> {
+        let dir = match std::env::var_os("GARMIN_TOKEN_DIR") {
+            Some(dir) => PathBuf::from(dir),
+            None => default_token_dir()?,
+        };
+        let path = dir.join(TOKEN_FILENAME);
+        let json = std::fs::read_to_string(&path).with_context(|| {
+            format!(
+                "Failed to read Garmin token {}. Run 'garmin auth login' first.",
+                path.display()
+            )
+        })?;
+        let token: OAuth2Token = serde_json::from_str(&json)
+            .with_context(|| format!("Failed to parse Garmin token {}", path.display()))?;
+
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64;
+        if token.expires_at > 0 && token.expires_at < now {
+            anyhow::bail!(
+                "Garmin token {} has expired. Run 'garmin auth login' to refresh it.",
+                path.display()
+            );
+        }
+
+        let base_url =
+            std::env::var("GARMIN_API_URL").unwrap_or_else(|_| DEFAULT_API_URL.to_string());
+        Ok(Self::new(http, &base_url, &token.access_token))
+    }
+
+    async fn get(&self, path: &str) -> Result<reqwest::Response> {
+        let url = format!("{}{path}", self.base_url);
+        let resp = self
+            .http
+            .get(&url)
+            .header(reqwest::header::AUTHORIZATION, &self.authorization)
+            .send()
+            .await
+            .with_context(|| format!("Failed to request {url}"))?;
+        resp.error_for_status()
+            .with_context(|| format!("Garmin API request {path} failed"))
+    }
+}
+
+impl GarminApi for GarminClient {
+    async fn list_activities(&self, start: usize, limit: usize) -> Result<Vec<ActivitySummary>> {
+        let path = format!(
+            "/activitylist-service/activities/search/activities?start={start}&limit={limit}"
+        );
+        self.get(&path)
+            .await?
+            .json()
+            .await
+            .context("Failed to parse activity list JSON")
+    }
+
+    async fn activity_details(&self, id: u64) -> Result<ActivityDetails> {
+        self.get(&format!(
+            "/activity-service/activity/{id}/details?maxChartSize=0&maxPolylineSize={MAX_POLYLINE_SIZE}"
+        ))
+            .await?
+            .json()
+            .await
+            .with_context(|| format!("Failed to parse activity {id} JSON"))
+    }
+
+    async fn download(&self, id: u64, format: DownloadFormat) -> Result<Vec<u8>> {
+        let path = match format {
+            DownloadFormat::Gpx => format!("/download-service/export/gpx/activity/{id}"),
+            DownloadFormat::Fit => format!("/download-service/files/activity/{id}"),
+        };
+        let bytes = self.get(&path).await?.bytes().await?.to_vec();
+        match format {
+            DownloadFormat::Gpx => Ok(bytes),
+            DownloadFormat::Fit => extract_fit(&bytes)
+                .with_context(|| format!("Failed to unpack original file for activity {id}")),
+        }
+    }
+}
+
+fn extract_fit(zip_bytes: &[u8]) -> Result<Vec<u8>> {
+    let mut archive = zip::ZipArchive::new(Cursor::new(zip_bytes))?;
+    for i in 0..archive.len() {
+        let mut file = archive.by_index(i)?;
+        if file.name().to_ascii_lowercase().ends_with(".fit") {
+            let mut buf = Vec::new();
+            file.read_to_end(&mut buf)?;
+            return Ok(buf);
+        }
+    }
+    anyhow::bail!("no .fit file in archive")
+}
+
+fn default_token_dir() -> Result<PathBuf> {
+    let data_dir = match std::env::var_os("XDG_DATA_HOME") {
+        Some(dir) => PathBuf::from(dir),
+        None => {
+            let home = std::env::var_os("HOME").context("HOME is not set")?;
+            if cfg!(target_os = "macos") {
+                PathBuf::from(home).join("Library/Application Support")
+            } else {
+                PathBuf::from(home).join(".local/share")
+            }
+        }
+    };
+    Ok(data_dir.join("garmin").join(TOKEN_PROFILE))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_server::{MockServer, fixture};
+
+    async fn serve(
+        handler: impl Fn(&str) -> (u16, Vec<u8>) + Send + Sync + 'static,
+    ) -> (MockServer, GarminClient) {
+        let server = MockServer::start(handler).await;
+        let client = GarminClient::new(reqwest::Client::new(), &server.url, "test-token");
+        (server, client)
+    }
+
+    #[tokio::test]
+    async fn lists_a_page_of_activities() {
+        let (server, client) = serve(|_| (200, fixture("garmin/activities_page_100.json"))).await;
+
+        let page = client.list_activities(100, 100).await.unwrap();
+
+        assert_eq!(page.len(), 20);
+        assert_eq!(page[0].id, 21000000020);
+        assert_eq!(page[0].date(), "2026-05-11");
+        assert_eq!(page[0].type_key(), "trail_running");
+        assert_eq!(page[0].distance, Some(18700.0));
+        let requests = server.requests();
+        assert!(requests[0].starts_with(
+            "GET /activitylist-service/activities/search/activities?start=100&limit=100 "
+        ));
+        assert!(
+            requests[0]
+                .to_ascii_lowercase()
+                .contains("authorization: bearer test-token")
+        );
+    }
+
+    #[tokio::test]
+    async fn reads_the_details_polyline() {
+        let (_server, client) = serve(|path| match path {
+            p if p.starts_with("/activity-service/activity/21000000120/details") => {
+                (200, fixture("garmin/details_in_area.json"))
+            }
+            _ => (200, fixture("garmin/details_no_polyline.json")),
+        })
+        .await;
+
+        let details = client.activity_details(21000000120).await.unwrap();
+        let polyline = details.polyline.unwrap();
+        assert_eq!(polyline.polyline.len(), 10);
+        assert_eq!(polyline.min_lat, Some(44.6521));
+
+        let details = client.activity_details(21000000117).await.unwrap();
+        assert!(details.polyline.is_none());
+    }
+
+    #[tokio::test]
+    async fn downloads_gpx_and_unpacks_fit() {
+        let (server, client) = serve(|path| match path {
+            "/download-service/export/gpx/activity/21000000120" => {
+                (200, fixture("garmin/activity.gpx"))
+            }
+            "/download-service/files/activity/21000000120" => {
+                (200, fixture("garmin/activity_original.zip"))
+            }
+            _ => (404, Vec::new()),
+        })
+        .await;
+
+        let gpx = client
+            .download(21000000120, DownloadFormat::Gpx)
+            .await
+            .unwrap();
+        assert_eq!(gpx, fixture("garmin/activity.gpx"));
+        let raw = TrackFormat::Gpx.read(gpx.as_slice()).unwrap();
+        assert_eq!(raw.tracks[0].len(), 10);
+
+        let fit = client
+            .download(21000000120, DownloadFormat::Fit)
+            .await
+            .unwrap();
+        assert_eq!(fit, fixture("fit/trail_run.fit"));
+        let raw = TrackFormat::Fit.read(fit.as_slice()).unwrap();
+        assert_eq!(raw.sport.as_deref(), Some("trail_running"));
+        assert_eq!(server.paths().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn reports_http_errors_with_their_status() {
+        let (_server, client) = serve(|_| (429, b"Too Many Requests".to_vec())).await;
+
+        let error = client.activity_details(1).await.unwrap_err();
+        let status = error
+            .downcast_ref::<reqwest::Error>()
+            .and_then(|e| e.status());
+        assert_eq!(status, Some(reqwest::StatusCode::TOO_MANY_REQUESTS));
+    }
+
+    #[test]
+    fn rejects_an_archive_without_a_fit_file() {
+        assert!(extract_fit(&fixture("garmin/activity.gpx")).is_err());
+    }
+}
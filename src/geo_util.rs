@@ -0,0 +1,25 @@
+use geo_types::LineString;
+
+pub const EARTH_RADIUS_M: f64 = 6_371_000.0;
+
+/// Great-circle distance between two points, in meters.
+pub fn haversine_m(lat1: f64, lon1: f64, lat2: f64, lon2: f64) -> f64 {
+    let (lat1, lon1, lat2, lon2) = (
+        lat1.to_radians(),
+        lon1.to_radians(),
+        lat2.to_radians(),
+        lon2.to_radians(),
+    );
+    let dlat = lat2 - lat1;
+    let dlon = lon2 - lon1;
+    let a = (dlat / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * (dlon / 2.0).sin().powi(2);
+    EARTH_RADIUS_M * 2.0 * a.sqrt().asin()
+}
+
+/// Length of a (lon, lat) line, in meters.
+pub fn linestring_length_m(line: &LineString<f64>) -> f64 {
+    line.0
+        .windows(2)
+        .map(|w| haversine_m(w[0].y, w[0].x, w[1].y, w[1].x))
+        .sum()
+}
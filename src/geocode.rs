@@ -0,0 +1,131 @@
+use anyhow::{Context, Result, bail};
+use geo_types::MultiPolygon;
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::boundary;
+use crate::config::GEOCODER_URL;
+
+/// One search result from a Nominatim-compatible geocoder (`format=jsonv2`).
+#[derive(Debug, Deserialize)]
+struct SearchResult {
+    display_name: String,
+    #[serde(default)]
+    osm_type: Option<String>,
+    #[serde(default)]
+    osm_id: Option<i64>,
+    #[serde(default)]
+    category: Option<String>,
+    #[serde(default, rename = "type")]
+    place_type: Option<String>,
+    #[serde(default)]
+    geojson: Option<Value>,
+}
+
+/// A place found by name, with its boundary polygon.
+pub struct Place {
+    pub display_name: String,
+    pub boundary: MultiPolygon<f64>,
+}
+
+/// Look up `query` and return the best match that has a boundary polygon, skipping
+/// results that are only a point (a village's centre, a peak...).
+///
+/// `GEOCODER_URL` overrides the geocoder base URL (e.g. to point at a local stand-in).
+pub async fn find_place(client: &reqwest::Client, query: &str) -> Result<Place> {
+    let base_url = std::env::var("GEOCODER_URL").unwrap_or_else(|_| GEOCODER_URL.to_string());
+    search(client, &base_url, query).await
+}
+
+async fn search(client: &reqwest::Client, base_url: &str, query: &str) -> Result<Place> {
+    eprintln!("Looking up \"{query}\" on {base_url}...");
+    let resp = client
+        .get(format!("{}/search", base_url.trim_end_matches('/')))
+        .query(&[
+            ("q", query),
+            ("format", "jsonv2"),
+            ("polygon_geojson", "1"),
+            ("limit", "10"),
+        ])
+        .send()
+        .await
+        .context("Failed to query the geocoder")?;
+    if !resp.status().is_success() {
+        bail!("Geocoder returned {}", resp.status());
+    }
+    let results: Vec<SearchResult> = resp
+        .json()
+        .await
+        .context("Failed to parse the geocoder response")?;
+
+    if results.is_empty() {
+        bail!("No place found for \"{query}\"");
+    }
+    for result in &results {
+        let Some(boundary) = result.geojson.as_ref().and_then(boundary::from_geojson) else {
+            continue;
+        };
+        eprintln!(
+            "Found {} ({} {}, {}={})",
+            result.display_name,
+            result.osm_type.as_deref().unwrap_or("?"),
+            result.osm_id.unwrap_or_default(),
+            result.category.as_deref().unwrap_or("?"),
+            result.place_type.as_deref().unwrap_or("?"),
+        );
+        return Ok(Place {
+            display_name: result.display_name.clone(),
+            boundary,
+        });
+    }
+
+    let names: Vec<&str> = results.iter().map(|r| r.display_name.as_str()).collect();
+    bail!(
+        "None of the {} places found for \"{query}\" has a boundary polygon: {}",
+        results.len(),
+        names.join("; ")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_server::{MockServer, fixture};
+
+    async fn search_fixture(name: &'static str) -> (MockServer, Result<Place>) {
+        let server = MockServer::start(move |_| (200, fixture(name))).await;
+        let result = search(&reqwest::Client::new(), &server.url, "Saou").await;
+        (server, result)
+    }
+
+    #[tokio::test]
+    async fn picks_the_first_result_with_a_polygon() {
+        let (server, place) = search_fixture("geocoder/search_saou.json").await;
+        let place = place.unwrap();
+        assert_eq!(place.display_name, "Forêt de Saou, Drôme, France");
+        let bounds = boundary::bounds_of(&place.boundary).unwrap();
+        assert_eq!((bounds.west, bounds.east), (5.05, 5.15));
+
+        let paths = server.paths();
+        assert_eq!(paths.len(), 1);
+        assert!(paths[0].starts_with("/search?q=Saou&format=jsonv2&polygon_geojson=1"));
+    }
+
+    #[tokio::test]
+    async fn fails_when_no_result_has_a_polygon() {
+        let (_server, place) = search_fixture("geocoder/search_points_only.json").await;
+        let error = place.err().unwrap().to_string();
+        assert!(error.contains("None of the 2 places"), "{error}");
+        assert!(error.contains("Roche Colombe, Drôme, France"), "{error}");
+    }
+
+    #[tokio::test]
+    async fn fails_when_nothing_is_found() {
+        let server = MockServer::start(|_| (200, b"[]".to_vec())).await;
+        let error = search(&reqwest::Client::new(), &server.url, "Nowhere")
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(error.to_string(), "No place found for \"Nowhere\"");
+    }
+}
@@ -1,39 +1,245 @@
+mod fit;
+mod geojson;
+mod kml;
+mod tcx;
+
 use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
 use geo_types::LineString;
 use std::fs::File;
-use std::io::BufReader;
+use std::io::{BufReader, Read};
 use std::path::Path;
 
+use crate::boundary::Area;
 use crate::config::*;
+use crate::metadata::{ActivityMeta, MetadataStore};
+use crate::sport;
 
 #[derive(Debug)]
 pub struct Activity {
     pub name: String,
-    pub tracks: Vec<LineString<f64>>,
+    pub tracks: Vec<Track>,
+    /// From the metadata index, if the file was synced or imported.
+    pub meta: Option<ActivityMeta>,
+    /// Sport type from the metadata index, or else from the file itself.
+    pub sport: Option<String>,
+    /// Earliest and latest point timestamps, if the file has any.
+    pub start_time: Option<DateTime<Utc>>,
+    pub end_time: Option<DateTime<Utc>>,
+}
+
+/// A continuous recorded track. `times` and `elevations` are parallel to `geometry`.
+#[derive(Debug, Clone)]
+pub struct Track {
+    pub geometry: LineString<f64>,
+    pub times: Vec<Option<DateTime<Utc>>>,
+    pub elevations: Vec<Option<f64>>,
+}
+
+impl Track {
+    pub fn from_points(points: &[TrackPoint]) -> Self {
+        Self {
+            geometry: points.iter().map(|p| (p.lon, p.lat)).collect(),
+            times: points.iter().map(|p| p.time).collect(),
+            elevations: points.iter().map(|p| p.ele).collect(),
+        }
+    }
+
+    pub fn points(&self) -> Vec<TrackPoint> {
+        self.geometry
+            .0
+            .iter()
+            .zip(&self.times)
+            .zip(&self.elevations)
+            .map(|((c, &time), &ele)| TrackPoint {
+                lon: c.x,
+                lat: c.y,
+                time,
+                ele,
+            })
+            .collect()
+    }
+
+    /// Total climb in meters, summed over consecutive points that both have an elevation.
+    pub fn elevation_gain(&self) -> f64 {
+        self.elevations
+            .windows(2)
+            .filter_map(|w| Some(w[1]? - w[0]?))
+            .filter(|d| *d > 0.0)
+            .fold(0.0, |total, d| total + d)
+    }
+}
+
+/// A point as read from a track file.
+#[derive(Debug, Clone, Copy)]
+pub struct TrackPoint {
+    pub lon: f64,
+    pub lat: f64,
+    pub time: Option<DateTime<Utc>>,
+    /// Meters above sea level.
+    pub ele: Option<f64>,
+}
+
+impl TrackPoint {
+    pub fn new(lon: f64, lat: f64) -> Self {
+        Self {
+            lon,
+            lat,
+            time: None,
+            ele: None,
+        }
+    }
+}
+
+/// What a reader extracts from a track file, before the bbox filter.
+#[derive(Debug, Default)]
+pub struct RawTracks {
+    pub name: Option<String>,
+    pub sport: Option<String>,
+    /// One point list per track segment.
+    pub tracks: Vec<Vec<TrackPoint>>,
+}
+
+impl RawTracks {
+    /// Whether any track would survive the area filter in `load_activities`.
+    pub fn touches_area(&self, area: &Area) -> bool {
+        self.tracks
+            .iter()
+            .any(|points| points.len() >= 2 && track_in_areas(points, std::slice::from_ref(area)))
+    }
+}
+
+/// Supported track file formats. Each has a reader in its own submodule.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TrackFormat {
+    Gpx,
+    Fit,
+    Tcx,
+    GeoJson,
+    Kml,
+}
+
+impl TrackFormat {
+    /// Detect the format from the file extension (ignoring a trailing `.gz`).
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let name = path.file_name()?.to_string_lossy().to_ascii_lowercase();
+        let name = name.strip_suffix(".gz").unwrap_or(&name);
+        match name.rsplit_once('.')?.1 {
+            "gpx" => Some(Self::Gpx),
+            "fit" => Some(Self::Fit),
+            "tcx" => Some(Self::Tcx),
+            "geojson" => Some(Self::GeoJson),
+            "kml" => Some(Self::Kml),
+            _ => None,
+        }
+    }
+
+    /// Detect the format from the first bytes of the (decompressed) content.
+    pub fn sniff(head: &[u8]) -> Option<Self> {
+        if head.len() >= 12 && &head[8..12] == b".FIT" {
+            return Some(Self::Fit);
+        }
+        let text = String::from_utf8_lossy(head);
+        let text = text.trim_start_matches('\u{feff}').trim_start();
+        if text.starts_with('<') {
+            if text.contains("<gpx") {
+                Some(Self::Gpx)
+            } else if text.contains("<TrainingCenterDatabase") {
+                Some(Self::Tcx)
+            } else if text.contains("<kml") {
+                Some(Self::Kml)
+            } else {
+                None
+            }
+        } else if text.starts_with('{')
+            && [
+                "\"Feature",
+                "\"LineString",
+                "\"MultiLineString",
+                "\"GeometryCollection",
+            ]
+            .iter()
+            .any(|kind| text.contains(kind))
+        {
+            Some(Self::GeoJson)
+        } else {
+            None
+        }
+    }
+
+    pub fn read(self, reader: impl Read) -> Result<RawTracks> {
+        match self {
+            Self::Gpx => read_gpx(reader),
+            Self::Fit => fit::read_tracks(reader),
+            Self::Tcx => tcx::read_tracks(reader),
+            Self::GeoJson => geojson::read_tracks(reader),
+            Self::Kml => kml::read_tracks(reader),
+        }
+    }
 }
 
-pub fn load_activities(dir: &str) -> Result<Vec<Activity>> {
+/// Load every track file in `dir`, keeping the tracks that pass through any of `areas`.
+pub fn load_activities(dir: &str, areas: &[Area]) -> Result<Vec<Activity>> {
     let dir_path = Path::new(dir);
     if !dir_path.exists() {
         anyhow::bail!("Activities directory '{dir}' does not exist. Run 'synclinal sync' first.");
     }
 
+    let metadata = MetadataStore::load(dir)?;
     let mut activities = Vec::new();
 
     let mut entries: Vec<_> = std::fs::read_dir(dir_path)
         .with_context(|| format!("Failed to read directory {dir}"))?
         .filter_map(|e| e.ok())
-        .filter(|e| e.path().extension().is_some_and(|ext| ext == "gpx"))
+        .filter(|e| e.path().is_file())
+        .filter(|e| !e.file_name().to_string_lossy().starts_with('.'))
+        .filter(|e| e.file_name() != METADATA_FILENAME)
         .collect();
     entries.sort_by_key(|e| e.file_name());
 
     for entry in entries {
         let path = entry.path();
-        match parse_gpx(&path) {
-            Ok(Some(activity)) => {
-                let total_points: usize = activity.tracks.iter().map(|t| t.0.len()).sum();
+        let format = match detect_format(&path) {
+            Ok(Some(format)) => format,
+            // Not a track file
+            Ok(None) => continue,
+            Err(e) => {
+                eprintln!("Warning: failed to read {}: {e}", path.display());
+                continue;
+            }
+        };
+        match parse_track_file(&path, format, areas) {
+            Ok(Some(mut activity)) => {
+                let file_name = entry.file_name().to_string_lossy().to_string();
+                activity.meta = metadata.by_file(&file_name).cloned();
+                if let Some(meta) = &activity.meta {
+                    if let Some(name) = &meta.name {
+                        activity.name = name.clone();
+                    }
+                    if meta.sport.is_some() {
+                        activity.sport = meta.sport.clone();
+                    }
+                }
+                if !sport::is_allowed(activity.sport.as_deref()) {
+                    eprintln!(
+                        "Skipping {} — sport type {} excluded",
+                        activity.name,
+                        activity.sport.as_deref().unwrap_or_default(),
+                    );
+                    continue;
+                }
+                let total_points: usize = activity.tracks.iter().map(|t| t.geometry.0.len()).sum();
+                let when = match (activity.start_time, activity.end_time) {
+                    (Some(start), Some(end)) => format!(
+                        "{}, {} min, ",
+                        start.format("%Y-%m-%d"),
+                        (end - start).num_minutes()
+                    ),
+                    _ => String::new(),
+                };
+                let gain: f64 = activity.tracks.iter().map(Track::elevation_gain).sum();
                 eprintln!(
-                    "Loaded {} — {} tracks, {} points",
+                    "Loaded {} — {when}{} tracks, {} points, +{gain:.0} m",
                     activity.name,
                     activity.tracks.len(),
                     total_points,
@@ -41,7 +247,7 @@ pub fn load_activities(dir: &str) -> Result<Vec<Activity>> {
                 activities.push(activity);
             }
             Ok(None) => {
-                eprintln!("Skipping {} — no tracks in bbox", path.display());
+                eprintln!("Skipping {} — no tracks in the area", path.display());
             }
             Err(e) => {
                 eprintln!("Warning: failed to parse {}: {e}", path.display());
@@ -53,46 +259,159 @@ pub fn load_activities(dir: &str) -> Result<Vec<Activity>> {
     Ok(activities)
 }
 
-fn parse_gpx(path: &Path) -> Result<Option<Activity>> {
+/// Find a file's track format, by extension or else by sniffing its content.
+pub fn detect_format(path: &Path) -> Result<Option<TrackFormat>> {
+    if let Some(format) = TrackFormat::from_path(path) {
+        return Ok(Some(format));
+    }
+    let mut head = Vec::with_capacity(512);
+    open_track(path)?.take(512).read_to_end(&mut head)?;
+    Ok(TrackFormat::sniff(&head))
+}
+
+/// Open a track file, transparently decompressing `.gz` files.
+pub fn open_track(path: &Path) -> Result<Box<dyn Read>> {
     let file = File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
     let reader = BufReader::new(file);
-    let gpx_data =
-        gpx::read(reader).with_context(|| format!("Failed to parse {}", path.display()))?;
+    if path.extension().is_some_and(|ext| ext == "gz") {
+        Ok(Box::new(flate2::read::GzDecoder::new(reader)))
+    } else {
+        Ok(Box::new(reader))
+    }
+}
+
+fn parse_track_file(path: &Path, format: TrackFormat, areas: &[Area]) -> Result<Option<Activity>> {
+    let raw = format
+        .read(open_track(path)?)
+        .with_context(|| format!("Failed to parse {}", path.display()))?;
 
-    let name = gpx_data
-        .metadata
-        .and_then(|m| m.name)
-        .or_else(|| path.file_stem().map(|s| s.to_string_lossy().to_string()))
-        .unwrap_or_default();
+    // Fall back to the file name without extensions (e.g. "123" for "123.fit.gz")
+    let name = raw.name.filter(|n| !n.is_empty()).unwrap_or_else(|| {
+        let file_name = path
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_default();
+        file_name.split('.').next().unwrap_or_default().to_string()
+    });
+
+    let mut activity = activity_in_areas(name, raw.tracks, areas);
+    if let Some(activity) = &mut activity {
+        activity.sport = raw.sport;
+    }
+    Ok(activity)
+}
 
-    let mut tracks = Vec::new();
+fn read_gpx(reader: impl Read) -> Result<RawTracks> {
+    let gpx_data = gpx::read(reader)?;
 
-    for track in &gpx_data.tracks {
-        for segment in &track.segments {
-            let coords: Vec<(f64, f64)> = segment
+    let tracks = gpx_data
+        .tracks
+        .iter()
+        .flat_map(|track| &track.segments)
+        .map(|segment| {
+            segment
                 .points
                 .iter()
-                .map(|p| (p.point().x(), p.point().y()))
-                .collect();
+                .map(|p| TrackPoint {
+                    lon: p.point().x(),
+                    lat: p.point().y(),
+                    time: p.time.and_then(|t| parse_time(&t.format().ok()?)),
+                    ele: p.elevation,
+                })
+                .collect()
+        })
+        .collect();
 
-            if coords.len() < 2 {
-                continue;
-            }
+    Ok(RawTracks {
+        name: gpx_data.metadata.and_then(|m| m.name),
+        sport: gpx_data.tracks.iter().find_map(|t| t.type_.clone()),
+        tracks,
+    })
+}
 
-            // Check if any point falls within the bbox
-            let in_bbox = coords.iter().any(|&(lon, lat)| {
-                (BBOX_SOUTH..=BBOX_NORTH).contains(&lat) && (BBOX_WEST..=BBOX_EAST).contains(&lon)
-            });
+/// Build an activity from raw tracks, keeping only tracks that touch one of the areas.
+fn activity_in_areas(
+    name: String,
+    tracks: Vec<Vec<TrackPoint>>,
+    areas: &[Area],
+) -> Option<Activity> {
+    let tracks: Vec<Track> = tracks
+        .iter()
+        .filter(|points| points.len() >= 2 && track_in_areas(points, areas))
+        .map(|points| Track::from_points(points))
+        .collect();
 
-            if in_bbox {
-                tracks.push(LineString::from(coords));
-            }
-        }
+    if tracks.is_empty() {
+        return None;
     }
 
-    if tracks.is_empty() {
-        return Ok(None);
+    let times = || tracks.iter().flat_map(|t| &t.times).flatten();
+    Some(Activity {
+        name,
+        start_time: times().min().copied(),
+        end_time: times().max().copied(),
+        tracks,
+        meta: None,
+        sport: None,
+    })
+}
+
+/// Check if any point falls within one of the areas
+fn track_in_areas(points: &[TrackPoint], areas: &[Area]) -> bool {
+    points
+        .iter()
+        .any(|p| areas.iter().any(|area| area.contains(p.lat, p.lon)))
+}
+
+/// Parse an RFC 3339 timestamp, as used by TCX, KML and GeoJSON tracks.
+fn parse_time(text: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(text.trim())
+        .ok()
+        .map(|t| t.to_utc())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sniffs_formats_from_content() {
+        let sniff = |head: &str| TrackFormat::sniff(head.as_bytes());
+        let mut fit = vec![14, 0x20, 0x5c, 0x08, 0, 0, 0, 0];
+        fit.extend_from_slice(b".FIT");
+        assert_eq!(TrackFormat::sniff(&fit), Some(TrackFormat::Fit));
+
+        assert_eq!(
+            sniff("\u{feff}<?xml version=\"1.0\"?>\n<gpx version=\"1.1\">"),
+            Some(TrackFormat::Gpx)
+        );
+        assert_eq!(
+            sniff("<?xml version=\"1.0\"?><TrainingCenterDatabase xmlns=\"\">"),
+            Some(TrackFormat::Tcx)
+        );
+        assert_eq!(
+            sniff("<kml xmlns=\"http://www.opengis.net/kml/2.2\">"),
+            Some(TrackFormat::Kml)
+        );
+        assert_eq!(sniff("<html><body>Not found</body></html>"), None);
+
+        for json in [
+            r#"{"type": "FeatureCollection", "features": []}"#,
+            r#"{"type": "LineString", "coordinates": []}"#,
+            r#"{"type": "MultiLineString", "coordinates": []}"#,
+            r#"{ "type": "GeometryCollection", "geometries": [] }"#,
+        ] {
+            assert_eq!(sniff(json), Some(TrackFormat::GeoJson), "{json}");
+        }
+        assert_eq!(sniff(r#"{"activityId": 1}"#), None);
     }
 
-    Ok(Some(Activity { name, tracks }))
+    #[test]
+    fn detects_formats_from_the_extension() {
+        let format = |name: &str| TrackFormat::from_path(Path::new(name));
+        assert_eq!(format("123.fit.gz"), Some(TrackFormat::Fit));
+        assert_eq!(format("Morning Run.GPX"), Some(TrackFormat::Gpx));
+        assert_eq!(format("trail.geojson"), Some(TrackFormat::GeoJson));
+        assert_eq!(format("metadata.json"), None);
+    }
 }
@@ -0,0 +1,187 @@
+use anyhow::Result;
+use fitparser::profile::MesgNum;
+use fitparser::{FitDataRecord, Value};
+use std::io::Read;
+
+use super::{RawTracks, TrackPoint};
+
+/// FIT stores positions as semicircles: 2^31 semicircles = 180 degrees.
+const SEMICIRCLES_TO_DEG: f64 = 180.0 / 2_147_483_648.0;
+
+/// Read the GPS positions of a FIT file as one track per timer start/stop run.
+///
+/// FIT files carry no activity name.
+pub fn read_tracks(mut reader: impl Read) -> Result<RawTracks> {
+    let records = fitparser::from_reader(&mut reader)?;
+
+    let mut tracks = Vec::new();
+    let mut current = Vec::new();
+    let mut sport = None;
+
+    for record in &records {
+        match record.kind() {
+            MesgNum::Record => {
+                if let Some(point) = record_point(record) {
+                    current.push(point);
+                }
+            }
+            MesgNum::Sport | MesgNum::Session if sport.is_none() => {
+                sport = record_sport(record);
+            }
+            // A timer stop splits the track, like a new <trkseg> in GPX
+            MesgNum::Event if is_timer_stop(record) && !current.is_empty() => {
+                tracks.push(std::mem::take(&mut current));
+            }
+            _ => {}
+        }
+    }
+    if !current.is_empty() {
+        tracks.push(current);
+    }
+
+    Ok(RawTracks {
+        name: None,
+        sport,
+        tracks,
+    })
+}
+
+fn record_point(record: &FitDataRecord) -> Option<TrackPoint> {
+    let lat = field_f64(record, "position_lat")?;
+    let lon = field_f64(record, "position_long")?;
+    let time = record
+        .fields()
+        .iter()
+        .find(|f| f.name() == "timestamp")
+        .and_then(|f| match f.value() {
+            Value::Timestamp(t) => Some(t.to_utc()),
+            _ => None,
+        });
+    // Newer devices only fill the 32-bit enhanced field
+    let ele = field_f64(record, "enhanced_altitude").or_else(|| field_f64(record, "altitude"));
+    Some(TrackPoint {
+        lon: lon * SEMICIRCLES_TO_DEG,
+        lat: lat * SEMICIRCLES_TO_DEG,
+        time,
+        ele,
+    })
+}
+
+/// Combine `sport` and `sub_sport` into a Garmin activity type key where they differ,
+/// e.g. running + trail = "trail_running".
+fn record_sport(record: &FitDataRecord) -> Option<String> {
+    let field = |name: &str| {
+        record
+            .fields()
+            .iter()
+            .find(|f| f.name() == name)
+            .map(|f| f.value().to_string())
+    };
+    let sport = field("sport")?;
+    let key = match (sport.as_str(), field("sub_sport").as_deref()) {
+        ("running", Some("trail")) => "trail_running".to_string(),
+        ("running", Some("treadmill")) => "treadmill_running".to_string(),
+        ("running", Some("virtual_activity")) => "virtual_run".to_string(),
+        ("cycling", Some("mountain")) => "mountain_biking".to_string(),
+        ("cycling", Some("indoor_cycling")) => "indoor_cycling".to_string(),
+        ("cycling", Some("virtual_activity")) => "virtual_ride".to_string(),
+        _ => sport,
+    };
+    Some(key)
+}
+
+fn is_timer_stop(record: &FitDataRecord) -> bool {
+    let field = |name: &str| {
+        record
+            .fields()
+            .iter()
+            .find(|f| f.name() == name)
+            .map(|f| f.value().to_string())
+    };
+    field("event").as_deref() == Some("timer")
+        && field("event_type")
+            .as_deref()
+            .is_some_and(|t| t.starts_with("stop"))
+}
+
+fn field_f64(record: &FitDataRecord, name: &str) -> Option<f64> {
+    let field = record.fields().iter().find(|f| f.name() == name)?;
+    match field.value() {
+        Value::Invalid => None,
+        value => value.clone().try_into().ok(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fitparser::FitDataField;
+
+    const FIT_FIXTURE: &str = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/fixtures/fit/trail_run.fit"
+    );
+
+    fn assert_near(actual: f64, expected: f64) {
+        assert!((actual - expected).abs() < 1e-6, "{actual} != {expected}");
+    }
+
+    #[test]
+    fn reads_points_split_at_timer_stops() {
+        let raw = read_tracks(std::fs::File::open(FIT_FIXTURE).unwrap()).unwrap();
+        assert_eq!(raw.name, None);
+        assert_eq!(raw.sport.as_deref(), Some("trail_running"));
+
+        // Stopped after three points; the first point after the restart has no fix yet
+        let lengths: Vec<_> = raw.tracks.iter().map(Vec::len).collect();
+        assert_eq!(lengths, [3, 2]);
+
+        let first = raw.tracks[0][0];
+        assert_near(first.lat, 44.65);
+        assert_near(first.lon, 5.14);
+        assert_near(first.ele.unwrap(), 412.4);
+        assert_eq!(
+            first.time.unwrap().to_rfc3339(),
+            "2026-06-30T08:00:00+00:00"
+        );
+        // Read from the 16-bit altitude field
+        assert_near(raw.tracks[0][2].ele.unwrap(), 415.6);
+
+        let last = raw.tracks[1][1];
+        assert_near(last.lat, 44.653);
+        assert_near(last.lon, 5.143);
+        assert_eq!(last.time.unwrap().to_rfc3339(), "2026-06-30T08:05:20+00:00");
+    }
+
+    #[test]
+    fn combines_sport_and_sub_sport() {
+        let sport = |sport: &str, sub_sport: Option<&str>| {
+            let mut record = FitDataRecord::new(MesgNum::Session);
+            let field = |name: &str, number, value: &str| {
+                FitDataField::new(
+                    name.to_string(),
+                    number,
+                    None,
+                    Value::String(value.to_string()),
+                    String::new(),
+                )
+            };
+            record.push(field("sport", 5, sport));
+            if let Some(sub_sport) = sub_sport {
+                record.push(field("sub_sport", 6, sub_sport));
+            }
+            record_sport(&record)
+        };
+        assert_eq!(
+            sport("running", Some("trail")).as_deref(),
+            Some("trail_running")
+        );
+        assert_eq!(
+            sport("cycling", Some("mountain")).as_deref(),
+            Some("mountain_biking")
+        );
+        assert_eq!(sport("cycling", Some("road")).as_deref(), Some("cycling"));
+        assert_eq!(sport("hiking", None).as_deref(), Some("hiking"));
+        assert_eq!(record_sport(&FitDataRecord::new(MesgNum::Session)), None);
+    }
+}
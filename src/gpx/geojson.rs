@@ -0,0 +1,144 @@
+use anyhow::{Context, Result};
+use serde_json::Value;
+use std::io::Read;
+
+use super::{RawTracks, TrackPoint};
+
+/// Read a GeoJSON file: one track per `LineString` (or part of a `MultiLineString`),
+/// whether bare, in a `Feature` or in a `FeatureCollection`.
+pub fn read_tracks(reader: impl Read) -> Result<RawTracks> {
+    let json: Value = serde_json::from_reader(reader)?;
+
+    let features: Vec<&Value> = match json["type"].as_str() {
+        Some("FeatureCollection") => json["features"]
+            .as_array()
+            .context("FeatureCollection without features")?
+            .iter()
+            .collect(),
+        _ => vec![&json],
+    };
+
+    let name = json["name"]
+        .as_str()
+        .or_else(|| {
+            features
+                .iter()
+                .find_map(|f| f["properties"]["name"].as_str())
+        })
+        .map(str::to_string);
+
+    let mut tracks = Vec::new();
+    for feature in features {
+        let (geometry, times) = match feature["type"].as_str() {
+            Some("Feature") => (&feature["geometry"], &feature["properties"]["coordTimes"]),
+            _ => (feature, &Value::Null),
+        };
+        collect_lines(geometry, times, &mut tracks);
+    }
+
+    Ok(RawTracks {
+        name,
+        sport: None,
+        tracks,
+    })
+}
+
+/// `times` is the `coordTimes` feature property written by most GPX→GeoJSON converters:
+/// one timestamp per coordinate, nested like the coordinates.
+fn collect_lines(geometry: &Value, times: &Value, tracks: &mut Vec<Vec<TrackPoint>>) {
+    match geometry["type"].as_str() {
+        Some("LineString") => tracks.push(parse_line(&geometry["coordinates"], times)),
+        Some("MultiLineString") => {
+            let lines = geometry["coordinates"].as_array().into_iter().flatten();
+            for (i, line) in lines.enumerate() {
+                tracks.push(parse_line(line, &times[i]));
+            }
+        }
+        Some("GeometryCollection") => {
+            for g in geometry["geometries"].as_array().into_iter().flatten() {
+                collect_lines(g, &Value::Null, tracks);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// `[[lon, lat, ele?], ...]`
+fn parse_line(coords: &Value, times: &Value) -> Vec<TrackPoint> {
+    coords
+        .as_array()
+        .into_iter()
+        .flatten()
+        .enumerate()
+        .filter_map(|(i, c)| {
+            Some(TrackPoint {
+                lon: c[0].as_f64()?,
+                lat: c[1].as_f64()?,
+                time: times[i].as_str().and_then(super::parse_time),
+                ele: c[2].as_f64(),
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_features_with_their_times() {
+        let json = r#"{
+            "type": "FeatureCollection",
+            "features": [
+                {
+                    "type": "Feature",
+                    "properties": {
+                        "name": "Grand Pomerolle",
+                        "coordTimes": ["2026-06-30T08:00:00Z", "2026-06-30T08:00:10Z"]
+                    },
+                    "geometry": {"type": "LineString", "coordinates": [[5.14, 44.65, 412.5], [5.141, 44.651]]}
+                },
+                {
+                    "type": "Feature",
+                    "properties": {"coordTimes": [["2026-06-30T09:00:00Z", "2026-06-30T09:00:10Z"], []]},
+                    "geometry": {
+                        "type": "MultiLineString",
+                        "coordinates": [[[5.15, 44.66], [5.151, 44.661]], [[5.16, 44.67], [5.161, 44.671]]]
+                    }
+                }
+            ]
+        }"#;
+        let raw = read_tracks(json.as_bytes()).unwrap();
+        assert_eq!(raw.name.as_deref(), Some("Grand Pomerolle"));
+        assert_eq!(raw.tracks.len(), 3);
+
+        let first = &raw.tracks[0];
+        assert_eq!(
+            (first[0].lon, first[0].lat, first[0].ele),
+            (5.14, 44.65, Some(412.5))
+        );
+        assert_eq!(first[1].ele, None);
+        assert_eq!(
+            first[1].time.unwrap().to_rfc3339(),
+            "2026-06-30T08:00:10+00:00"
+        );
+        assert_eq!(
+            raw.tracks[1][0].time.unwrap().to_rfc3339(),
+            "2026-06-30T09:00:00+00:00"
+        );
+        assert_eq!(raw.tracks[2][0].time, None);
+    }
+
+    #[test]
+    fn reads_a_bare_geometry() {
+        let json =
+            r#"{"type": "MultiLineString", "coordinates": [[[5.15, 44.66], [5.151, 44.661]]]}"#;
+        let raw = read_tracks(json.as_bytes()).unwrap();
+        assert_eq!(raw.name, None);
+        assert_eq!(raw.tracks.len(), 1);
+        assert_eq!(
+            (raw.tracks[0][1].lon, raw.tracks[0][1].lat),
+            (5.151, 44.661)
+        );
+    }
+}
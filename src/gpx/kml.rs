@@ -0,0 +1,138 @@
+use anyhow::Result;
+use std::io::Read;
+
+use super::{RawTracks, TrackPoint};
+
+/// Read a KML file: one track per `<LineString>` or `<gx:Track>`.
+pub fn read_tracks(mut reader: impl Read) -> Result<RawTracks> {
+    let mut xml = String::new();
+    reader.read_to_string(&mut xml)?;
+    let doc = roxmltree::Document::parse(&xml)?;
+
+    // The document name, or else the first placemark's that has one
+    let name_of = |node: roxmltree::Node| {
+        node.children()
+            .find(|c| c.has_tag_name("name"))
+            .and_then(|n| n.text())
+            .map(|t| t.trim().to_string())
+    };
+    let name = doc
+        .descendants()
+        .filter(|n| n.has_tag_name("Document"))
+        .find_map(name_of)
+        .or_else(|| {
+            doc.descendants()
+                .filter(|n| n.has_tag_name("Placemark"))
+                .find_map(name_of)
+        });
+
+    let mut tracks = Vec::new();
+    for node in doc.descendants() {
+        match node.tag_name().name() {
+            // <coordinates>lon,lat[,alt] lon,lat[,alt] ...</coordinates>
+            "LineString" => {
+                let coords = node
+                    .children()
+                    .find(|n| n.has_tag_name("coordinates"))
+                    .and_then(|n| n.text())
+                    .unwrap_or_default();
+                tracks.push(
+                    coords
+                        .split_whitespace()
+                        .filter_map(|tuple| parse_coord(tuple.split(',')))
+                        .collect(),
+                );
+            }
+            // <when>time</when>... then <gx:coord>lon lat [alt]</gx:coord>..., one per sample
+            "Track" => {
+                let times: Vec<_> = node
+                    .children()
+                    .filter(|n| n.has_tag_name("when"))
+                    .map(|n| n.text().and_then(super::parse_time))
+                    .collect();
+                tracks.push(
+                    node.children()
+                        .filter(|n| n.has_tag_name("coord"))
+                        .enumerate()
+                        .filter_map(|(i, n)| {
+                            let mut point = parse_coord(n.text()?.split_whitespace())?;
+                            point.time = times.get(i).copied().flatten();
+                            Some(point)
+                        })
+                        .collect(),
+                );
+            }
+            _ => {}
+        }
+    }
+
+    Ok(RawTracks {
+        name,
+        sport: None,
+        tracks,
+    })
+}
+
+fn parse_coord<'a>(mut parts: impl Iterator<Item = &'a str>) -> Option<TrackPoint> {
+    let lon = parts.next()?.trim().parse().ok()?;
+    let lat = parts.next()?.trim().parse().ok()?;
+    let mut point = TrackPoint::new(lon, lat);
+    point.ele = parts.next().and_then(|alt| alt.trim().parse().ok());
+    Some(point)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KML: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<kml xmlns="http://www.opengis.net/kml/2.2" xmlns:gx="http://www.google.com/kml/ext/2.2">
+  <Document>
+    <Placemark>
+      <LineString><coordinates>5.14,44.65,412.5 5.141,44.651</coordinates></LineString>
+    </Placemark>
+    <Placemark>
+      <name> Trois Becs </name>
+      <gx:Track>
+        <when>2026-06-30T08:00:00Z</when>
+        <when>2026-06-30T08:00:10Z</when>
+        <gx:coord>5.15 44.66 800</gx:coord>
+        <gx:coord>5.151 44.661 805</gx:coord>
+      </gx:Track>
+    </Placemark>
+  </Document>
+</kml>"#;
+
+    #[test]
+    fn reads_line_strings_and_tracks() {
+        let raw = read_tracks(KML.as_bytes()).unwrap();
+        assert_eq!(raw.tracks.len(), 2);
+
+        let line = &raw.tracks[0];
+        assert_eq!(
+            (line[0].lon, line[0].lat, line[0].ele),
+            (5.14, 44.65, Some(412.5))
+        );
+        assert_eq!((line[1].ele, line[1].time), (None, None));
+
+        let track = &raw.tracks[1];
+        assert_eq!(
+            (track[1].lon, track[1].lat, track[1].ele),
+            (5.151, 44.661, Some(805.0))
+        );
+        assert_eq!(
+            track[1].time.unwrap().to_rfc3339(),
+            "2026-06-30T08:00:10+00:00"
+        );
+    }
+
+    #[test]
+    fn names_by_the_document_else_the_first_named_placemark() {
+        let raw = read_tracks(KML.as_bytes()).unwrap();
+        assert_eq!(raw.name.as_deref(), Some("Trois Becs"));
+
+        let named = KML.replace("<Document>", "<Document><name>Saou</name>");
+        let raw = read_tracks(named.as_bytes()).unwrap();
+        assert_eq!(raw.name.as_deref(), Some("Saou"));
+    }
+}
@@ -0,0 +1,116 @@
+use anyhow::Result;
+use std::io::Read;
+
+use super::{RawTracks, TrackPoint};
+
+/// Read a Garmin Training Center (TCX) file: one track per `<Track>` element.
+pub fn read_tracks(mut reader: impl Read) -> Result<RawTracks> {
+    let mut xml = String::new();
+    reader.read_to_string(&mut xml)?;
+    let doc = roxmltree::Document::parse(&xml)?;
+
+    let activity = doc.descendants().find(|n| n.has_tag_name("Activity"));
+    let sport = activity
+        .and_then(|a| a.attribute("Sport"))
+        .map(str::to_string);
+    // The notes, or else the id (the start time); `Id` comes first in the schema
+    let name = activity
+        .and_then(|activity| child_text(activity, "Notes").or_else(|| child_text(activity, "Id")))
+        .map(str::to_string);
+
+    let tracks = doc
+        .descendants()
+        .filter(|n| n.has_tag_name("Track"))
+        .map(|track| {
+            track
+                .children()
+                .filter(|n| n.has_tag_name("Trackpoint"))
+                .filter_map(|point| {
+                    let position = point.children().find(|n| n.has_tag_name("Position"))?;
+                    Some(TrackPoint {
+                        lon: child_f64(position, "LongitudeDegrees")?,
+                        lat: child_f64(position, "LatitudeDegrees")?,
+                        time: child_text(point, "Time").and_then(super::parse_time),
+                        ele: child_f64(point, "AltitudeMeters"),
+                    })
+                })
+                .collect()
+        })
+        .collect();
+
+    Ok(RawTracks {
+        name,
+        sport,
+        tracks,
+    })
+}
+
+fn child_text<'a>(node: roxmltree::Node<'a, '_>, tag: &str) -> Option<&'a str> {
+    Some(
+        node.children()
+            .find(|n| n.has_tag_name(tag))?
+            .text()?
+            .trim(),
+    )
+}
+
+fn child_f64(node: roxmltree::Node, tag: &str) -> Option<f64> {
+    child_text(node, tag)?.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TCX: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<TrainingCenterDatabase xmlns="http://www.garmin.com/xmlschemas/TrainingCenterDatabase/v2">
+  <Activities>
+    <Activity Sport="Running">
+      <Id>2026-06-30T08:00:00Z</Id>
+      <Lap StartTime="2026-06-30T08:00:00Z">
+        <Track>
+          <Trackpoint>
+            <Time>2026-06-30T08:00:00Z</Time>
+            <Position><LatitudeDegrees>44.65</LatitudeDegrees><LongitudeDegrees>5.14</LongitudeDegrees></Position>
+            <AltitudeMeters>412.5</AltitudeMeters>
+          </Trackpoint>
+          <Trackpoint>
+            <Time>2026-06-30T08:00:05Z</Time>
+          </Trackpoint>
+          <Trackpoint>
+            <Time>2026-06-30T08:00:10Z</Time>
+            <Position><LatitudeDegrees>44.651</LatitudeDegrees><LongitudeDegrees>5.141</LongitudeDegrees></Position>
+          </Trackpoint>
+        </Track>
+      </Lap>
+      <Notes>Roche Colombe loop</Notes>
+    </Activity>
+  </Activities>
+</TrainingCenterDatabase>"#;
+
+    #[test]
+    fn reads_points_sport_and_notes() {
+        let raw = read_tracks(TCX.as_bytes()).unwrap();
+        assert_eq!(raw.name.as_deref(), Some("Roche Colombe loop"));
+        assert_eq!(raw.sport.as_deref(), Some("Running"));
+        assert_eq!(raw.tracks.len(), 1);
+
+        // The point without a position is skipped
+        let points = &raw.tracks[0];
+        assert_eq!(points.len(), 2);
+        assert_eq!((points[0].lon, points[0].lat), (5.14, 44.65));
+        assert_eq!(points[0].ele, Some(412.5));
+        assert_eq!(points[1].ele, None);
+        assert_eq!(
+            points[1].time.unwrap().to_rfc3339(),
+            "2026-06-30T08:00:10+00:00"
+        );
+    }
+
+    #[test]
+    fn names_the_activity_by_its_id_without_notes() {
+        let tcx = TCX.replace("<Notes>Roche Colombe loop</Notes>", "");
+        let raw = read_tracks(tcx.as_bytes()).unwrap();
+        assert_eq!(raw.name.as_deref(), Some("2026-06-30T08:00:00Z"));
+    }
+}
@@ -1,8 +1,8 @@
-use crate::config::*;
+use crate::boundary::Area;
+use crate::geo_util::{EARTH_RADIUS_M, haversine_m};
 use crate::matching::{COVERED_THRESHOLD, SegmentCoverage};
 use crate::osm::Segment;
 
-const EARTH_RADIUS_M: f64 = 6_371_000.0;
 const DISCRETIZE_STEP_M: f64 = 20.0;
 
 /// Grid metadata: origin, cell deltas in lat/lon, dimensions.
@@ -25,7 +25,8 @@ pub struct Cell {
     pub visited: bool,
     pub trail_km: f64,
     pub covered_km: f64,
-    pub segment_ids: Vec<usize>,
+    /// Ids of the segments passing through the cell.
+    pub segment_ids: Vec<String>,
 }
 
 pub struct GridResult {
@@ -40,20 +41,22 @@ pub fn compute_grid(
     segments: &[Segment],
     coverage: &[SegmentCoverage],
     cell_size_m: f64,
+    area: &Area,
 ) -> GridResult {
+    let bounds = &area.bounds;
     // Convert cell size to lat/lon deltas at the center of the bbox
-    let center_lat = (BBOX_SOUTH + BBOX_NORTH) / 2.0;
+    let center_lat = bounds.center_lat();
     let dlat = cell_size_m / EARTH_RADIUS_M * (180.0 / std::f64::consts::PI);
     let dlon = cell_size_m / (EARTH_RADIUS_M * center_lat.to_radians().cos())
         * (180.0 / std::f64::consts::PI);
 
-    let cols = ((BBOX_EAST - BBOX_WEST) / dlon).ceil() as usize;
-    let rows = ((BBOX_NORTH - BBOX_SOUTH) / dlat).ceil() as usize;
+    let cols = ((bounds.east - bounds.west) / dlon).ceil() as usize;
+    let rows = ((bounds.north - bounds.south) / dlat).ceil() as usize;
 
     let config = GridConfig {
         cell_size_m,
-        origin_lon: BBOX_WEST,
-        origin_lat: BBOX_SOUTH,
+        origin_lon: bounds.west,
+        origin_lat: bounds.south,
         dlat,
         dlon,
         cols,
@@ -85,7 +88,7 @@ pub fn compute_grid(
         let mut seen_cells = std::collections::HashSet::new();
 
         for (lat, lon) in &points {
-            if let Some(cell_id) = point_to_cell(*lat, *lon, &config) {
+            if let Some(cell_id) = point_to_cell(*lat, *lon, &config, area) {
                 seen_cells.insert(cell_id);
             }
         }
@@ -109,8 +112,8 @@ pub fn compute_grid(
             if is_covered {
                 cell.visited = true;
             }
-            if !cell.segment_ids.contains(&seg_idx) {
-                cell.segment_ids.push(seg_idx);
+            if !cell.segment_ids.contains(&seg.id) {
+                cell.segment_ids.push(seg.id.clone());
             }
         }
 
@@ -141,8 +144,9 @@ pub fn compute_grid(
     }
 }
 
-fn point_to_cell(lat: f64, lon: f64, config: &GridConfig) -> Option<usize> {
-    if !(BBOX_SOUTH..=BBOX_NORTH).contains(&lat) || !(BBOX_WEST..=BBOX_EAST).contains(&lon) {
+/// Cells outside the area's boundary never get any trail, so they drop out of the grid.
+fn point_to_cell(lat: f64, lon: f64, config: &GridConfig, area: &Area) -> Option<usize> {
+    if !area.contains(lat, lon) {
         return None;
     }
     let col = ((lon - config.origin_lon) / config.dlon).floor() as usize;
@@ -187,16 +191,3 @@ fn discretize_linestring(geom: &geo_types::LineString<f64>, step_m: f64) -> Vec<
 
     points
 }
-
-fn haversine_m(lat1: f64, lon1: f64, lat2: f64, lon2: f64) -> f64 {
-    let (lat1, lon1, lat2, lon2) = (
-        lat1.to_radians(),
-        lon1.to_radians(),
-        lat2.to_radians(),
-        lon2.to_radians(),
-    );
-    let dlat = lat2 - lat1;
-    let dlon = lon2 - lon1;
-    let a = (dlat / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * (dlon / 2.0).sin().powi(2);
-    EARTH_RADIUS_M * 2.0 * a.sqrt().asin()
-}
@@ -1,24 +1,52 @@
+mod batch;
+mod boundary;
+mod cleaning;
 mod config;
 mod export;
+mod fs_util;
 mod garmin;
+mod geo_util;
+mod geocode;
 mod gpx;
 mod grid;
+mod hash;
 mod matching;
+mod metadata;
+#[cfg(test)]
+mod mock_server;
 mod osm;
+mod osm_diff;
+mod osm_file;
+mod osm_graph;
+mod region;
 mod render;
+mod sport;
+mod strava;
 mod tiles;
+mod trail_filter;
+mod watch;
 
-use anyhow::Result;
+use anyhow::{Context, Result, bail};
 use clap::{Parser, Subcommand, ValueEnum};
+use std::path::Path;
+use std::slice;
 
 #[derive(Parser)]
 #[command(
     name = "synclinal",
-    about = "Trail coverage visualizer for the Synclinal de Saou"
+    about = "Trail coverage visualizer for the Synclinal de Saou and other areas"
 )]
 struct Cli {
     #[command(subcommand)]
     command: Commands,
+
+    /// Region config file
+    #[arg(long, global = true, default_value = config::REGIONS_PATH)]
+    config: String,
+
+    /// Region to work on [default: the config's default region]
+    #[arg(short, long, global = true)]
+    region: Option<String>,
 }
 
 #[derive(Subcommand)]
@@ -29,9 +57,31 @@ enum Commands {
         #[arg(short, long, default_value = "activities")]
         activities_dir: String,
 
-        /// Only sync activities since this date (YYYY-MM-DD)
-        #[arg(short, long, default_value = "2026-01-01")]
-        since: String,
+        /// Only sync activities since this date (YYYY-MM-DD) [default: since last sync]
+        #[arg(short, long)]
+        since: Option<String>,
+
+        /// Only sync activities up to this date (YYYY-MM-DD)
+        #[arg(long)]
+        until: Option<String>,
+
+        /// File format to download
+        #[arg(short, long, default_value = "gpx")]
+        format: FileFormat,
+
+        /// Re-check activities previously rejected as indoor, without GPS or too far away
+        #[arg(long)]
+        recheck: bool,
+    },
+
+    /// Import activities from a Strava bulk export archive
+    ImportStrava {
+        /// Path to the export zip (contains activities.csv and an activities/ folder)
+        archive: String,
+
+        /// Directory to store track files
+        #[arg(short, long, default_value = "activities")]
+        activities_dir: String,
     },
 
     /// Render trail coverage map
@@ -44,17 +94,21 @@ enum Commands {
         #[arg(short, long, default_value = "output/synclinal.png")]
         output: String,
 
-        /// Tile zoom level
-        #[arg(short, long, default_value_t = config::DEFAULT_ZOOM)]
-        zoom: u32,
+        /// Tile zoom level [default: from the region]
+        #[arg(short, long)]
+        zoom: Option<u32>,
 
-        /// Tile provider
-        #[arg(short = 'p', long, default_value = "opentopomap")]
-        tile_provider: TileProvider,
+        /// Tile provider [default: from the region]
+        #[arg(short = 'p', long)]
+        tile_provider: Option<TileProvider>,
 
         /// Clear cached data before rendering
         #[arg(long)]
         no_cache: bool,
+
+        /// Smooth GPS tracks with a Kalman filter before matching
+        #[arg(long)]
+        smooth: bool,
     },
 
     /// Debug: render map with raw GPS dots overlay
@@ -67,13 +121,13 @@ enum Commands {
         #[arg(short, long, default_value = "output/debug.png")]
         output: String,
 
-        /// Tile zoom level
-        #[arg(short, long, default_value_t = config::DEFAULT_ZOOM)]
-        zoom: u32,
+        /// Tile zoom level [default: from the region]
+        #[arg(short, long)]
+        zoom: Option<u32>,
 
-        /// Tile provider
-        #[arg(short = 'p', long, default_value = "opentopomap")]
-        tile_provider: TileProvider,
+        /// Tile provider [default: from the region]
+        #[arg(short = 'p', long)]
+        tile_provider: Option<TileProvider>,
     },
 
     /// Export segments and grid data as JSON for the web UI
@@ -89,6 +143,41 @@ enum Commands {
         /// Grid cell size in meters
         #[arg(long, default_value_t = 200.0)]
         grid_size: f64,
+
+        /// Smooth GPS tracks with a Kalman filter before matching
+        #[arg(long)]
+        smooth: bool,
+    },
+
+    /// Watch the activities directory and re-export (and re-render) on changes
+    Watch {
+        /// Directory containing track files
+        #[arg(short, long, default_value = "activities")]
+        activities_dir: String,
+
+        /// Output JSON file path
+        #[arg(short, long, default_value = "web/data.json")]
+        output: String,
+
+        /// Grid cell size in meters
+        #[arg(long, default_value_t = 200.0)]
+        grid_size: f64,
+
+        /// Smooth GPS tracks with a Kalman filter before matching
+        #[arg(long)]
+        smooth: bool,
+
+        /// Also re-render the PNG map to this path
+        #[arg(long)]
+        render: Option<String>,
+
+        /// Tile zoom level (with --render) [default: from the region]
+        #[arg(short, long)]
+        zoom: Option<u32>,
+
+        /// Tile provider (with --render) [default: from the region]
+        #[arg(short = 'p', long)]
+        tile_provider: Option<TileProvider>,
     },
 
     /// Sync new activities from Garmin and re-render the map
@@ -97,21 +186,73 @@ enum Commands {
         #[arg(short, long, default_value = "activities")]
         activities_dir: String,
 
-        /// Only sync activities since this date (YYYY-MM-DD)
-        #[arg(short, long, default_value = "2026-01-01")]
-        since: String,
+        /// Only sync activities since this date (YYYY-MM-DD) [default: since last sync]
+        #[arg(short, long)]
+        since: Option<String>,
+
+        /// Only sync activities up to this date (YYYY-MM-DD)
+        #[arg(long)]
+        until: Option<String>,
+
+        /// File format to download
+        #[arg(short, long, default_value = "gpx")]
+        format: FileFormat,
+
+        /// Re-check activities previously rejected as indoor, without GPS or too far away
+        #[arg(long)]
+        recheck: bool,
 
         /// Output file path
         #[arg(short, long, default_value = "output/synclinal.png")]
         output: String,
 
-        /// Tile zoom level
-        #[arg(short, long, default_value_t = config::DEFAULT_ZOOM)]
-        zoom: u32,
+        /// Tile zoom level [default: from the region]
+        #[arg(short, long)]
+        zoom: Option<u32>,
+
+        /// Tile provider [default: from the region]
+        #[arg(short = 'p', long)]
+        tile_provider: Option<TileProvider>,
+
+        /// Smooth GPS tracks with a Kalman filter before matching
+        #[arg(long)]
+        smooth: bool,
+
+        /// Update every configured region, writing `<id>.png` next to --output and
+        /// `<id>.json` plus `index.json` into --web-dir
+        #[arg(long)]
+        all_regions: bool,
+
+        /// Web UI directory (with --all-regions)
+        #[arg(long, default_value = "web")]
+        web_dir: String,
+
+        /// Grid cell size in meters (with --all-regions)
+        #[arg(long, default_value_t = 200.0)]
+        grid_size: f64,
+    },
+
+    /// Manage the configured regions
+    Region {
+        #[command(subcommand)]
+        action: RegionAction,
+    },
+}
+
+#[derive(Subcommand)]
+enum RegionAction {
+    /// Look up a place by name and add its boundary to the config as a new region
+    Add {
+        /// Place name, e.g. "Forêt de Saou"
+        name: String,
 
-        /// Tile provider
-        #[arg(short = 'p', long, default_value = "opentopomap")]
-        tile_provider: TileProvider,
+        /// Config key of the new region [default: derived from the name]
+        #[arg(long)]
+        id: Option<String>,
+
+        /// Title shown on the map [default: the name]
+        #[arg(long)]
+        title: Option<String>,
     },
 }
 
@@ -121,10 +262,31 @@ enum TileProvider {
     Opentopomap,
 }
 
-fn resolve_provider(tp: &TileProvider) -> tiles::Provider {
+fn provider_flag(tp: &Option<TileProvider>) -> Option<tiles::Provider> {
     match tp {
-        TileProvider::Openstreetmap => tiles::Provider::OpenStreetMap,
-        TileProvider::Opentopomap => tiles::Provider::OpenTopoMap,
+        Some(TileProvider::Openstreetmap) => Some(tiles::Provider::OpenStreetMap),
+        Some(TileProvider::Opentopomap) => Some(tiles::Provider::OpenTopoMap),
+        None => None,
+    }
+}
+
+/// The `--tile-provider` flag if given, else the region's provider.
+fn resolve_provider(tp: &Option<TileProvider>, region: &region::Region) -> tiles::Provider {
+    provider_flag(tp).unwrap_or(region.tile_provider)
+}
+
+#[derive(Clone, ValueEnum)]
+enum FileFormat {
+    /// GPX export
+    Gpx,
+    /// Original FIT file, with timestamps, HR and lap data
+    Fit,
+}
+
+fn resolve_format(format: &FileFormat) -> garmin::DownloadFormat {
+    match format {
+        FileFormat::Gpx => garmin::DownloadFormat::Gpx,
+        FileFormat::Fit => garmin::DownloadFormat::Fit,
     }
 }
 
@@ -135,29 +297,170 @@ fn build_client() -> Result<reqwest::Client> {
 }
 
 async fn do_render(
+    region: &region::Region,
+    area: &boundary::Area,
     activities_dir: &str,
     output: &str,
     zoom: u32,
     provider: tiles::Provider,
+    smooth: bool,
 ) -> Result<()> {
     let client = build_client()?;
-    let (_trails, segments) = osm::fetch_trails(&client).await?;
-    let activities = gpx::load_activities(activities_dir)?;
+    let (_trails, segments) = osm::fetch_trails(&client, region, area).await?;
+    let activities = cleaning::clean_activities(
+        gpx::load_activities(activities_dir, slice::from_ref(area))?,
+        smooth,
+    );
     let coverage = matching::compute_coverage(&segments, &activities);
-    let tile_map = tiles::fetch_and_stitch(&client, zoom, provider).await?;
-    render::render_png(&tile_map, &segments, &coverage, output)
+    let tile_map = tiles::fetch_and_stitch(&client, &area.bounds, zoom, provider).await?;
+    render::render_png(&tile_map, &segments, &coverage, &region.title, output)
+}
+
+/// Geocode `name` and append it to the region config, with its boundary saved as GeoJSON.
+async fn add_region(
+    config_path: &str,
+    name: &str,
+    id: Option<String>,
+    title: Option<String>,
+) -> Result<()> {
+    let id = id.unwrap_or_else(|| region::slug(name));
+    if id.is_empty() {
+        bail!("Can't derive a region id from \"{name}\"; pass --id");
+    }
+    if !region::is_valid_id(&id) {
+        bail!("Invalid region id '{id}': use lowercase letters, digits, '-' and '_'");
+    }
+    if Path::new(config_path).exists()
+        && region::RegionConfig::load(config_path)?
+            .regions
+            .contains_key(&id)
+    {
+        bail!("Region '{id}' already exists in {config_path}");
+    }
+
+    let place = geocode::find_place(&build_client()?, name).await?;
+    let bounds = boundary::bounds_of(&place.boundary)
+        .with_context(|| format!("The boundary of {} is empty", place.display_name))?;
+    let boundary_path = Path::new(config::BOUNDARIES_DIR).join(format!("{id}.geojson"));
+    boundary::write_geojson(&boundary_path, &place.boundary)?;
+
+    let region = region::Region {
+        id,
+        title: title.unwrap_or_else(|| name.to_string()),
+        bounds: None,
+        boundary: Some(region::Boundary::Geojson(
+            boundary_path.to_string_lossy().to_string(),
+        )),
+        cache_dir: None,
+        zoom: tiles::suggest_zoom(&bounds),
+        tile_provider: tiles::Provider::default(),
+        trails: None,
+        osm_file: None,
+        osm_cache_days: None,
+        overpass_urls: None,
+        min_segment_m: None,
+        merge_same_tags: None,
+    };
+    region::RegionConfig::append_region(config_path, &region)?;
+    eprintln!(
+        "Added region '{}' to {config_path}: boundary in {}, extent {:.5},{:.5} to {:.5},{:.5}, zoom {}",
+        region.id,
+        boundary_path.display(),
+        bounds.south,
+        bounds.west,
+        bounds.north,
+        bounds.east,
+        region.zoom
+    );
+    Ok(())
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
     let cli = Cli::parse();
+    // Region management works on the config file itself, before any region is selected
+    if let Commands::Region { action } = cli.command {
+        return match action {
+            RegionAction::Add { name, id, title } => {
+                add_region(&cli.config, &name, id, title).await
+            }
+        };
+    }
+    let regions = region::RegionConfig::load(&cli.config)?;
+
+    if let Commands::Update {
+        all_regions: true,
+        activities_dir,
+        since,
+        until,
+        format,
+        recheck,
+        output,
+        zoom,
+        tile_provider,
+        smooth,
+        web_dir,
+        grid_size,
+    } = cli.command
+    {
+        if cli.region.is_some() {
+            bail!("--region and --all-regions can't be used together");
+        }
+        let client = build_client()?;
+        let (loaded, areas) = batch::load_areas(&client, &regions).await?;
+        let api = garmin::GarminClient::from_env(client.clone())?;
+        garmin::sync(
+            &api,
+            &activities_dir,
+            &areas,
+            since.as_deref(),
+            until.as_deref(),
+            resolve_format(&format),
+            recheck,
+        )
+        .await?;
+        let activities =
+            cleaning::clean_activities(gpx::load_activities(&activities_dir, &areas)?, smooth);
+        let options = batch::BatchOptions {
+            output: &output,
+            web_dir: &web_dir,
+            grid_size,
+            zoom,
+            provider: provider_flag(&tile_provider),
+        };
+        batch::update_all(&client, &loaded, &areas, &activities, &options).await?;
+        return Ok(());
+    }
+
+    let region = regions.region(cli.region.as_deref())?;
+    let area = region.load_area(&build_client()?).await?;
 
     match cli.command {
         Commands::Sync {
             activities_dir,
             since,
+            until,
+            format,
+            recheck,
         } => {
-            garmin::sync(&activities_dir, &since)?;
+            let api = garmin::GarminClient::from_env(build_client()?)?;
+            garmin::sync(
+                &api,
+                &activities_dir,
+                slice::from_ref(&area),
+                since.as_deref(),
+                until.as_deref(),
+                resolve_format(&format),
+                recheck,
+            )
+            .await?;
+        }
+
+        Commands::ImportStrava {
+            archive,
+            activities_dir,
+        } => {
+            strava::import_archive(&archive, &activities_dir, &area)?;
         }
 
         Commands::Render {
@@ -166,16 +469,20 @@ async fn main() -> Result<()> {
             zoom,
             tile_provider,
             no_cache,
+            smooth,
         } => {
             if no_cache {
-                osm::clear_cache();
+                osm::clear_cache(region);
                 tiles::clear_cache();
             }
             do_render(
+                region,
+                &area,
                 &activities_dir,
                 &output,
-                zoom,
-                resolve_provider(&tile_provider),
+                zoom.unwrap_or(region.zoom),
+                resolve_provider(&tile_provider, region),
+                smooth,
             )
             .await?;
         }
@@ -187,9 +494,14 @@ async fn main() -> Result<()> {
             tile_provider,
         } => {
             let client = build_client()?;
-            let (_trails, segments) = osm::fetch_trails(&client).await?;
-            let tile_map =
-                tiles::fetch_and_stitch(&client, zoom, resolve_provider(&tile_provider)).await?;
+            let (_trails, segments) = osm::fetch_trails(&client, region, &area).await?;
+            let tile_map = tiles::fetch_and_stitch(
+                &client,
+                &area.bounds,
+                zoom.unwrap_or(region.zoom),
+                resolve_provider(&tile_provider, region),
+            )
+            .await?;
             render::render_debug_png(&tile_map, &segments, &output)?;
         }
 
@@ -197,31 +509,90 @@ async fn main() -> Result<()> {
             activities_dir,
             output,
             grid_size,
+            smooth,
         } => {
             let client = build_client()?;
-            let (_trails, segments) = osm::fetch_trails(&client).await?;
-            let activities = gpx::load_activities(&activities_dir)?;
+            let (_trails, segments) = osm::fetch_trails(&client, region, &area).await?;
+            let activities = cleaning::clean_activities(
+                gpx::load_activities(&activities_dir, slice::from_ref(&area))?,
+                smooth,
+            );
             let coverage = matching::compute_coverage(&segments, &activities);
-            let grid_result = grid::compute_grid(&segments, &coverage, grid_size);
-            export::export_json(&segments, &coverage, &grid_result, &output)?;
+            let grid_result = grid::compute_grid(&segments, &coverage, grid_size, &area);
+            export::export_json(&segments, &coverage, &grid_result, region, &area, &output)?;
+        }
+
+        Commands::Watch {
+            activities_dir,
+            output,
+            grid_size,
+            smooth,
+            render,
+            zoom,
+            tile_provider,
+        } => {
+            let client = build_client()?;
+            let (_trails, segments) = osm::fetch_trails(&client, region, &area).await?;
+            let tile_map = match &render {
+                Some(_) => Some(
+                    tiles::fetch_and_stitch(
+                        &client,
+                        &area.bounds,
+                        zoom.unwrap_or(region.zoom),
+                        resolve_provider(&tile_provider, region),
+                    )
+                    .await?,
+                ),
+                None => None,
+            };
+            let options = watch::WatchOptions {
+                region,
+                area: &area,
+                activities_dir: &activities_dir,
+                json_output: &output,
+                grid_size,
+                smooth,
+                render: tile_map.as_ref().zip(render.as_deref()),
+            };
+            watch::watch(&segments, &options).await?;
         }
 
         Commands::Update {
             activities_dir,
             since,
+            until,
+            format,
+            recheck,
             output,
             zoom,
             tile_provider,
+            smooth,
+            ..
         } => {
-            garmin::sync(&activities_dir, &since)?;
+            let api = garmin::GarminClient::from_env(build_client()?)?;
+            garmin::sync(
+                &api,
+                &activities_dir,
+                slice::from_ref(&area),
+                since.as_deref(),
+                until.as_deref(),
+                resolve_format(&format),
+                recheck,
+            )
+            .await?;
             do_render(
+                region,
+                &area,
                 &activities_dir,
                 &output,
-                zoom,
-                resolve_provider(&tile_provider),
+                zoom.unwrap_or(region.zoom),
+                resolve_provider(&tile_provider, region),
+                smooth,
             )
             .await?;
         }
+
+        Commands::Region { .. } => unreachable!("handled before selecting a region"),
     }
 
     Ok(())
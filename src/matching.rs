@@ -1,12 +1,12 @@
 use geo_types::LineString;
 
+use crate::geo_util::{EARTH_RADIUS_M, haversine_m, linestring_length_m};
 use crate::gpx::Activity;
 use crate::osm::Segment;
 
 const MATCH_THRESHOLD_M: f64 = 10.0;
 const TRAIL_STEP_M: f64 = 5.0;
 const GPX_STEP_M: f64 = 2.0;
-const EARTH_RADIUS_M: f64 = 6_371_000.0;
 const GRID_CELL_M: f64 = 20.0;
 pub const COVERED_THRESHOLD: f64 = 0.5;
 
@@ -101,7 +101,7 @@ fn build_gps_index(activities: &[Activity]) -> GpsIndex {
 
     for activity in activities {
         for track in &activity.tracks {
-            let interpolated = discretize(track, GPX_STEP_M);
+            let interpolated = discretize(&track.geometry, GPX_STEP_M);
             for (lat, lon) in &interpolated {
                 let cell = lat_lon_to_cell(*lat, *lon);
                 cells.entry(cell).or_default().push((*lat, *lon));
@@ -157,25 +157,3 @@ fn discretize(geom: &LineString<f64>, step_m: f64) -> Vec<(f64, f64)> {
 
     points
 }
-
-// --- Geometry helpers ---
-
-fn haversine_m(lat1: f64, lon1: f64, lat2: f64, lon2: f64) -> f64 {
-    let (lat1, lon1, lat2, lon2) = (
-        lat1.to_radians(),
-        lon1.to_radians(),
-        lat2.to_radians(),
-        lon2.to_radians(),
-    );
-    let dlat = lat2 - lat1;
-    let dlon = lon2 - lon1;
-    let a = (dlat / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * (dlon / 2.0).sin().powi(2);
-    EARTH_RADIUS_M * 2.0 * a.sqrt().asin()
-}
-
-fn linestring_length_m(geom: &LineString<f64>) -> f64 {
-    geom.0
-        .windows(2)
-        .map(|w| haversine_m(w[0].y, w[0].x, w[1].y, w[1].x))
-        .sum()
-}
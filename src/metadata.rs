@@ -0,0 +1,143 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::config::METADATA_FILENAME;
+
+/// `checkpoint` writes the index once this many entries were added since the last save.
+const SAVE_EVERY: usize = 25;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Source {
+    Garmin,
+    Strava,
+}
+
+/// What we know about an activity besides its track, recorded when it was downloaded.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ActivityMeta {
+    pub id: String,
+    pub name: Option<String>,
+    /// YYYY-MM-DD, local time.
+    pub date: Option<String>,
+    /// Source sport type, e.g. "trail_running" (Garmin) or "Run" (Strava).
+    pub sport: Option<String>,
+    pub distance_m: Option<f64>,
+    pub duration_s: Option<f64>,
+    pub source: Source,
+    /// File name within the activities directory.
+    pub file: String,
+}
+
+/// Metadata for the files in an activities directory, kept in `metadata.json` next to them.
+pub struct MetadataStore {
+    path: PathBuf,
+    /// Keyed by activity id.
+    entries: BTreeMap<String, ActivityMeta>,
+    /// File name → activity id.
+    by_file: HashMap<String, String>,
+    unsaved: usize,
+}
+
+impl MetadataStore {
+    pub fn load(activities_dir: &str) -> Result<Self> {
+        let path = Path::new(activities_dir).join(METADATA_FILENAME);
+        let entries: BTreeMap<String, ActivityMeta> = if path.exists() {
+            let json = fs::read_to_string(&path)?;
+            serde_json::from_str(&json)
+                .with_context(|| format!("Failed to parse {}", path.display()))?
+        } else {
+            BTreeMap::new()
+        };
+        let by_file = entries
+            .values()
+            .map(|meta| (meta.file.clone(), meta.id.clone()))
+            .collect();
+        Ok(Self {
+            path,
+            entries,
+            by_file,
+            unsaved: 0,
+        })
+    }
+
+    pub fn save(&mut self) -> Result<()> {
+        let json = serde_json::to_string_pretty(&self.entries)?;
+        fs::write(&self.path, json)
+            .with_context(|| format!("Failed to write {}", self.path.display()))?;
+        self.unsaved = 0;
+        Ok(())
+    }
+
+    /// Save if enough entries were added since the last save, so a long sync rewrites the
+    /// index now and then rather than after every activity.
+    pub fn checkpoint(&mut self) -> Result<()> {
+        if self.unsaved >= SAVE_EVERY {
+            self.save()?;
+        }
+        Ok(())
+    }
+
+    pub fn contains(&self, id: &str) -> bool {
+        self.entries.contains_key(id)
+    }
+
+    pub fn insert(&mut self, meta: ActivityMeta) {
+        let (id, file) = (meta.id.clone(), meta.file.clone());
+        // Re-downloaded in another format: the old file name no longer points here
+        if let Some(old) = self.entries.insert(id.clone(), meta)
+            && old.file != file
+        {
+            self.by_file.remove(&old.file);
+        }
+        self.by_file.insert(file, id);
+        self.unsaved += 1;
+    }
+
+    /// Look up the entry for a file in the activities directory.
+    pub fn by_file(&self, file: &str) -> Option<&ActivityMeta> {
+        self.by_file.get(file).and_then(|id| self.entries.get(id))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn meta(id: &str, file: &str) -> ActivityMeta {
+        ActivityMeta {
+            id: id.to_string(),
+            name: None,
+            date: None,
+            sport: None,
+            distance_m: None,
+            duration_s: None,
+            source: Source::Garmin,
+            file: file.to_string(),
+        }
+    }
+
+    #[test]
+    fn finds_entries_by_file_after_reload() {
+        let dir = std::env::temp_dir().join(format!("synclinal-metadata-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let dir = dir.to_string_lossy().to_string();
+
+        let mut store = MetadataStore::load(&dir).unwrap();
+        store.insert(meta("1", "1.gpx"));
+        store.insert(meta("2", "2.fit"));
+        // Re-downloaded as FIT
+        store.insert(meta("1", "1.fit"));
+        assert!(store.by_file("1.gpx").is_none());
+        assert_eq!(store.by_file("1.fit").unwrap().id, "1");
+        store.save().unwrap();
+
+        let store = MetadataStore::load(&dir).unwrap();
+        assert_eq!(store.by_file("2.fit").unwrap().id, "2");
+        assert!(store.by_file("3.gpx").is_none());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
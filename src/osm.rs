@@ -1,26 +1,85 @@
-use anyhow::{Context, Result};
-use geo_types::LineString;
-use serde::Deserialize;
-use std::collections::{HashMap, HashSet};
+use anyhow::{Context, Result, anyhow, bail};
+use chrono::{DateTime, Utc};
+use geo_types::{Coord, LineString, MultiPolygon};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
+use crate::boundary::{self, Area};
 use crate::config::*;
+use crate::hash::fnv1a;
+use crate::osm_diff::WayDiff;
+use crate::osm_file;
+use crate::osm_graph::{self, TrailGraph};
+use crate::region::Region;
+use crate::trail_filter::TrailFilter;
+
+/// Rounds over all mirrors before giving up, with a growing delay between rounds.
+const OVERPASS_ROUNDS: u32 = 3;
+const OVERPASS_RETRY_DELAY: Duration = Duration::from_secs(5);
+/// A bit over the `[timeout:60]` asked of the server.
+const OVERPASS_REQUEST_TIMEOUT: Duration = Duration::from_secs(90);
 
 #[derive(Debug, Clone)]
 #[allow(dead_code)]
 pub struct Trail {
     pub id: i64,
-    pub name: Option<String>,
+    /// The way's tags listed in `KEPT_TAGS`.
+    pub tags: BTreeMap<String, String>,
     pub geometry: LineString<f64>,
 }
 
+/// An OSM way with the coordinates of its nodes, from Overpass or an extract file.
+pub struct Way {
+    pub id: i64,
+    pub tags: HashMap<String, String>,
+    pub nodes: Vec<i64>,
+    /// (lon, lat) of each node.
+    pub coords: Vec<(f64, f64)>,
+}
+
 /// A segment is a portion of a trail between two intersection nodes (or endpoints).
 #[derive(Debug, Clone)]
 pub struct Segment {
+    /// Stable id from the way id and end nodes, e.g. `w123:n45-n67`, so the same stretch
+    /// of trail keeps its id across OSM refreshes. Set once segments are final.
+    pub id: String,
+    /// The OSM way this segment is part of.
+    pub way_id: i64,
+    /// OSM node ids at each end; `None` where the boundary cut the segment between nodes.
+    pub start_node: Option<i64>,
+    pub end_node: Option<i64>,
+    /// The way's tags listed in `KEPT_TAGS`.
+    pub tags: BTreeMap<String, String>,
     pub geometry: LineString<f64>,
 }
 
+impl Segment {
+    /// The same segment walked the other way.
+    pub fn reversed(mut self) -> Self {
+        self.geometry.0.reverse();
+        std::mem::swap(&mut self.start_node, &mut self.end_node);
+        self
+    }
+
+    /// A `highway=path` segment with no id yet, for tests.
+    #[cfg(test)]
+    pub fn path(way_id: i64, start: Option<i64>, end: Option<i64>, coords: &[(f64, f64)]) -> Self {
+        Self {
+            id: String::new(),
+            way_id,
+            start_node: start,
+            end_node: end,
+            tags: BTreeMap::from([("highway".to_string(), "path".to_string())]),
+            geometry: coords.to_vec().into(),
+        }
+    }
+}
+
 #[derive(Deserialize)]
 struct OverpassResponse {
     elements: Vec<OverpassElement>,
@@ -37,6 +96,19 @@ struct OverpassElement {
     nodes: Option<Vec<i64>>,
     #[serde(default)]
     geometry: Option<Vec<OverpassLatLon>>,
+    #[serde(default)]
+    members: Option<Vec<OverpassMember>>,
+}
+
+/// A relation member, with its geometry inlined by `out geom`.
+#[derive(Deserialize)]
+struct OverpassMember {
+    #[serde(rename = "type")]
+    member_type: String,
+    #[serde(default)]
+    role: String,
+    #[serde(default)]
+    geometry: Option<Vec<OverpassLatLon>>,
 }
 
 #[derive(Deserialize)]
@@ -45,75 +117,504 @@ struct OverpassLatLon {
     lon: f64,
 }
 
-pub fn clear_cache() {
-    let path = Path::new(OSM_CACHE_PATH);
-    if path.exists() {
-        if let Err(e) = fs::remove_file(path) {
-            eprintln!("Warning: failed to remove {OSM_CACHE_PATH}: {e}");
+/// Remove the region's cached trails, for every trail filter.
+pub fn clear_cache(region: &Region) {
+    let Ok(entries) = fs::read_dir(region.cache_dir()) else {
+        return;
+    };
+    for path in entries.flatten().map(|e| e.path()) {
+        let is_cache = path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .is_some_and(|n| n.starts_with(OSM_CACHE_PREFIX) && n.ends_with(".json"));
+        if !is_cache {
+            continue;
+        }
+        if let Err(e) = fs::remove_file(&path) {
+            eprintln!("Warning: failed to remove {}: {e}", path.display());
         } else {
-            eprintln!("Cleared OSM cache");
+            eprintln!("Cleared OSM cache {}", path.display());
         }
     }
 }
 
-pub async fn fetch_trails(client: &reqwest::Client) -> Result<(Vec<Trail>, Vec<Segment>)> {
-    let cache_path = Path::new(OSM_CACHE_PATH);
-    if cache_path.exists() {
-        eprintln!("Loading cached OSM data from {OSM_CACHE_PATH}");
-        let data = fs::read_to_string(cache_path)?;
-        return parse_overpass_json(&data);
+/// Written next to each cached Overpass response.
+#[derive(Serialize, Deserialize)]
+struct CacheMeta {
+    fetched_at: DateTime<Utc>,
+    query: String,
+}
+
+fn cache_path(region: &Region) -> PathBuf {
+    region.cache_dir().join(format!(
+        "{OSM_CACHE_PREFIX}-{}.json",
+        region.trail_filter().cache_key()
+    ))
+}
+
+/// Trails in the region's bbox matching its trail filter, with segments clipped to the
+/// area's boundary. They come from the region's OSM extract if it has one, else from
+/// Overpass (or its cache).
+pub async fn fetch_trails(
+    client: &reqwest::Client,
+    region: &Region,
+    area: &Area,
+) -> Result<(Vec<Trail>, Vec<Segment>)> {
+    let filter = region.trail_filter();
+    let ways = match &region.osm_file {
+        Some(path) => {
+            eprintln!("Reading trails for {} from {path}...", region.title);
+            osm_file::read_ways(Path::new(path), &filter, &area.bounds)?
+        }
+        None => parse_overpass_json(&overpass_trails(client, region, area).await?)?,
+    };
+    let (trails, segments) = split_ways(ways, &filter);
+    let mut segments = osm_graph::normalize_segments(
+        clip_segments(segments, area),
+        region.min_segment_m(),
+        region.merge_same_tags(),
+    );
+    assign_segment_ids(&mut segments);
+    TrailGraph::new(&segments).print_summary();
+    Ok((trails, segments))
+}
+
+/// The Overpass response for the region's trails, from the cache unless it has expired
+/// or was made by another query. A refresh reports how the trails changed; if it fails,
+/// the stale cache is used.
+async fn overpass_trails(client: &reqwest::Client, region: &Region, area: &Area) -> Result<String> {
+    let cache_path = cache_path(region);
+    let meta_path = cache_path.with_extension("meta.json");
+    let query = region.trail_filter().overpass_query(&area.bounds);
+
+    let cached = if cache_path.exists() {
+        let body = fs::read_to_string(&cache_path)?;
+        let fetched_at = cache_fetched_at(&cache_path, &meta_path, &query);
+        match fetched_at {
+            Some(at) if Utc::now() - at < region.osm_cache_max_age() => {
+                eprintln!(
+                    "Loading cached OSM data from {} (fetched {})",
+                    cache_path.display(),
+                    at.format("%Y-%m-%d")
+                );
+                return Ok(body);
+            }
+            Some(at) => eprintln!(
+                "OSM cache {} is from {}, refreshing",
+                cache_path.display(),
+                at.format("%Y-%m-%d")
+            ),
+            None => eprintln!(
+                "OSM cache {} was made by another query, refreshing",
+                cache_path.display()
+            ),
+        }
+        Some((body, fetched_at))
+    } else {
+        None
+    };
+
+    eprintln!("Fetching trails for {} from Overpass API...", region.title);
+    let body = match query_overpass(client, &region.overpass_urls(), &query).await {
+        Ok(body) => body,
+        Err(e) => match cached {
+            Some((body, _)) => {
+                eprintln!("Warning: {e:#}; using the cached trails");
+                return Ok(body);
+            }
+            None => {
+                return Err(e.context(format!(
+                    "Failed to fetch the trails of region '{}'",
+                    region.id
+                )));
+            }
+        },
+    };
+
+    let meta = CacheMeta {
+        fetched_at: Utc::now(),
+        query,
+    };
+    if let Some(parent) = cache_path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(&cache_path, &body)?;
+    fs::write(&meta_path, serde_json::to_string_pretty(&meta)?)?;
+    eprintln!("Cached OSM data to {}", cache_path.display());
+
+    if let Some((old_body, old_fetched_at)) = cached {
+        report_changes(region, &old_body, &body, old_fetched_at, meta.fetched_at);
+    }
+    Ok(body)
+}
+
+/// When the cache was fetched, from its metadata (or the file's age for caches written
+/// before there was any), or `None` if it was made by a different query.
+fn cache_fetched_at(cache_path: &Path, meta_path: &Path, query: &str) -> Option<DateTime<Utc>> {
+    match fs::read_to_string(meta_path)
+        .ok()
+        .and_then(|json| serde_json::from_str::<CacheMeta>(&json).ok())
+    {
+        Some(meta) if meta.query == query => Some(meta.fetched_at),
+        Some(_) => None,
+        None => {
+            let modified = fs::metadata(cache_path).and_then(|m| m.modified()).ok()?;
+            Some(modified.into())
+        }
     }
+}
 
-    let query = format!(
-        r#"[out:json][timeout:60];
-(
-  way["highway"="path"]({s},{w},{n},{e});
-  way["highway"="track"]({s},{w},{n},{e});
-  way["highway"="footway"]({s},{w},{n},{e});
-);
-out geom;"#,
-        s = BBOX_SOUTH,
-        w = BBOX_WEST,
-        n = BBOX_NORTH,
-        e = BBOX_EAST,
+/// Compare the trail ways of two fetches, print the differences and save them to the
+/// region's `OSM_CHANGES_FILENAME`. Problems are only warned about: the new data is
+/// already cached.
+fn report_changes(
+    region: &Region,
+    old_body: &str,
+    new_body: &str,
+    old_fetched_at: Option<DateTime<Utc>>,
+    fetched_at: DateTime<Utc>,
+) {
+    let filter = region.trail_filter();
+    let trail_ways = |body: &str| -> Result<Vec<Way>> {
+        Ok(parse_overpass_json(body)?
+            .into_iter()
+            .filter(|way| filter.matches(&way.tags))
+            .collect())
+    };
+    let old_ways = match trail_ways(old_body) {
+        Ok(ways) => ways,
+        Err(e) => {
+            eprintln!("Warning: can't compare with the previous OSM data: {e:#}");
+            return;
+        }
+    };
+    let Ok(new_ways) = trail_ways(new_body) else {
+        return;
+    };
+
+    let format = |at: DateTime<Utc>| at.format("%Y-%m-%d %H:%M UTC").to_string();
+    let diff = WayDiff::new(
+        &old_ways,
+        &new_ways,
+        old_fetched_at.map(format),
+        format(fetched_at),
     );
+    if diff.is_empty() {
+        eprintln!("No OSM trail changes since the previous fetch");
+        return;
+    }
+    diff.print();
+    let path = region.cache_dir().join(OSM_CHANGES_FILENAME);
+    match diff.write(&path) {
+        Ok(()) => eprintln!("Wrote OSM changes to {}", path.display()),
+        Err(e) => eprintln!("Warning: {e:#}"),
+    }
+}
+
+/// Fetch the outline of an OSM boundary relation, e.g. a natural park.
+pub async fn fetch_boundary(
+    client: &reqwest::Client,
+    urls: &[String],
+    relation_id: i64,
+) -> Result<MultiPolygon<f64>> {
+    eprintln!("Fetching boundary of OSM relation {relation_id} from Overpass API...");
+    let query = format!("[out:json][timeout:60];\nrelation({relation_id});\nout geom;");
+    let body = query_overpass(client, urls, &query)
+        .await
+        .with_context(|| format!("Failed to fetch OSM relation {relation_id}"))?;
+    let response: OverpassResponse =
+        serde_json::from_str(&body).context("Failed to parse Overpass JSON")?;
 
-    eprintln!("Fetching trails from Overpass API...");
+    let relation = response
+        .elements
+        .iter()
+        .find(|e| e.elem_type == "relation" && e.id == relation_id)
+        .with_context(|| format!("OSM relation {relation_id} not found"))?;
+
+    let mut outer = Vec::new();
+    let mut inner = Vec::new();
+    for member in relation.members.iter().flatten() {
+        let Some(geometry) = member
+            .geometry
+            .as_ref()
+            .filter(|_| member.member_type == "way")
+        else {
+            continue;
+        };
+        let coords: Vec<Coord<f64>> = geometry
+            .iter()
+            .map(|p| Coord { x: p.lon, y: p.lat })
+            .collect();
+        match member.role.as_str() {
+            "inner" => inner.push(coords),
+            _ => outer.push(coords),
+        }
+    }
+
+    boundary::from_ways(outer, inner)
+        .with_context(|| format!("Failed to build the boundary of OSM relation {relation_id}"))
+}
+
+/// Why an Overpass request failed: worth asking again (here or on another mirror), or not.
+enum OverpassError {
+    Transient(anyhow::Error),
+    Fatal(anyhow::Error),
+}
+
+/// Run a query on the first of `urls`, moving on to the next mirror when one is busy or
+/// returns a broken response. Only a complete, non-empty JSON result is returned, so
+/// callers can cache it.
+async fn query_overpass(client: &reqwest::Client, urls: &[String], query: &str) -> Result<String> {
+    let mut delay = OVERPASS_RETRY_DELAY;
+    for round in 1..=OVERPASS_ROUNDS {
+        for url in urls {
+            match query_mirror(client, url, query).await {
+                Ok(body) => return Ok(body),
+                Err(OverpassError::Fatal(e)) => {
+                    return Err(e.context(format!("Overpass query failed on {url}")));
+                }
+                Err(OverpassError::Transient(e)) => eprintln!("Warning: {url}: {e:#}"),
+            }
+        }
+        if round < OVERPASS_ROUNDS {
+            eprintln!(
+                "Every Overpass mirror failed (round {round}/{OVERPASS_ROUNDS}), retrying in {}s",
+                delay.as_secs()
+            );
+            tokio::time::sleep(delay).await;
+            delay *= 2;
+        }
+    }
+    bail!("Failed to query Overpass API: every mirror failed {OVERPASS_ROUNDS} times")
+}
+
+async fn query_mirror(
+    client: &reqwest::Client,
+    url: &str,
+    query: &str,
+) -> Result<String, OverpassError> {
+    let transient = |e: anyhow::Error| OverpassError::Transient(e);
     let resp = client
-        .post("https://overpass-api.de/api/interpreter")
-        .form(&[("data", &query)])
+        .post(url)
+        .form(&[("data", query)])
+        .timeout(OVERPASS_REQUEST_TIMEOUT)
         .send()
         .await
-        .context("Failed to query Overpass API")?;
+        .map_err(|e| transient(anyhow!(e).context("request failed")))?;
+    let status = resp.status();
+    let body = resp
+        .text()
+        .await
+        .map_err(|e| transient(anyhow!(e).context("reading the response failed")))?;
 
-    let body = resp.text().await?;
+    if matches!(
+        status,
+        StatusCode::TOO_MANY_REQUESTS
+            | StatusCode::BAD_GATEWAY
+            | StatusCode::SERVICE_UNAVAILABLE
+            | StatusCode::GATEWAY_TIMEOUT
+    ) {
+        return Err(transient(anyhow!("server busy ({status})")));
+    }
+    if !status.is_success() {
+        return Err(OverpassError::Fatal(anyhow!(
+            "query rejected ({status}): {}",
+            snippet(&body)
+        )));
+    }
+    validate_response(&body)?;
+    Ok(body)
+}
 
-    if let Some(parent) = cache_path.parent() {
-        fs::create_dir_all(parent)?;
+/// Overpass reports some failures with a 200: an HTML error page, a `remark` with a
+/// runtime error (timeout, out of memory) next to partial results, or a cut-off body.
+fn validate_response(body: &str) -> Result<(), OverpassError> {
+    let json: Value = match serde_json::from_str(body) {
+        Ok(json) => json,
+        Err(_) if body.trim_start().starts_with('<') => {
+            return Err(OverpassError::Transient(anyhow!(
+                "returned HTML instead of JSON: {}",
+                snippet(body)
+            )));
+        }
+        Err(e) => {
+            return Err(OverpassError::Transient(anyhow!(
+                "truncated or invalid JSON response ({} bytes): {e}",
+                body.len()
+            )));
+        }
+    };
+    if let Some(remark) = json["remark"].as_str()
+        && remark.contains("error")
+    {
+        return Err(OverpassError::Transient(anyhow!(
+            "incomplete result: {remark}"
+        )));
+    }
+    match json["elements"].as_array() {
+        None => Err(OverpassError::Transient(anyhow!(
+            "response has no elements list"
+        ))),
+        Some(elements) if elements.is_empty() => Err(OverpassError::Fatal(anyhow!(
+            "the result is empty (nothing matched in the queried area)"
+        ))),
+        Some(_) => Ok(()),
     }
-    fs::write(cache_path, &body)?;
-    eprintln!("Cached OSM data to {OSM_CACHE_PATH}");
+}
 
-    parse_overpass_json(&body)
+/// The start of a response body as one line of text without HTML tags, for error messages.
+fn snippet(body: &str) -> String {
+    let mut in_tag = false;
+    let stripped: String = body
+        .chars()
+        .filter(|&c| {
+            match c {
+                '<' => in_tag = true,
+                '>' if in_tag => {
+                    in_tag = false;
+                    return false;
+                }
+                _ => {}
+            }
+            !in_tag
+        })
+        .collect();
+    let text = stripped.split_whitespace().collect::<Vec<_>>().join(" ");
+    match text.char_indices().nth(200) {
+        Some((i, _)) => format!("{}...", &text[..i]),
+        None => text,
+    }
 }
 
-fn parse_overpass_json(json: &str) -> Result<(Vec<Trail>, Vec<Segment>)> {
+/// Cut segments to the area's boundary, dropping the parts outside it. A segment that
+/// leaves and re-enters the area becomes several segments.
+fn clip_segments(segments: Vec<Segment>, area: &Area) -> Vec<Segment> {
+    if area.boundary.is_none() {
+        return segments;
+    }
+    let before = segments.len();
+    let clipped: Vec<Segment> = segments
+        .iter()
+        .flat_map(|segment| {
+            let (first, last) = (segment.geometry.0.first(), segment.geometry.0.last());
+            area.clip(&segment.geometry)
+                .into_iter()
+                .filter(|geometry| geometry.0.len() >= 2)
+                .map(move |geometry| Segment {
+                    id: String::new(),
+                    way_id: segment.way_id,
+                    start_node: segment
+                        .start_node
+                        .filter(|_| same_point(geometry.0.first(), first)),
+                    end_node: segment
+                        .end_node
+                        .filter(|_| same_point(geometry.0.last(), last)),
+                    tags: segment.tags.clone(),
+                    geometry,
+                })
+        })
+        .collect();
+    eprintln!(
+        "Clipped {before} segments to the boundary: {} remain",
+        clipped.len()
+    );
+    clipped
+}
+
+/// Give each segment an id from its way id and end nodes, in a fixed order so reversing
+/// a segment doesn't change it. An end cut by the boundary has no node and uses a hash of
+/// its coordinate instead. Segments that would share an id (a way joining the same two
+/// nodes twice) each get a hash of their geometry appended, `~<hash>`, so the ids don't
+/// depend on the order the segments come in.
+fn assign_segment_ids(segments: &mut [Segment]) {
+    let ids: Vec<String> = segments
+        .iter()
+        .map(|segment| {
+            let points = &segment.geometry.0;
+            let mut ends = [
+                end_key(segment.start_node, points.first()),
+                end_key(segment.end_node, points.last()),
+            ];
+            ends.sort();
+            format!("w{}:{}-{}", segment.way_id, ends[0], ends[1])
+        })
+        .collect();
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    for id in &ids {
+        *counts.entry(id).or_default() += 1;
+    }
+    for (segment, id) in segments.iter_mut().zip(&ids) {
+        segment.id = if counts[id.as_str()] > 1 {
+            format!("{id}~{:016x}", geometry_hash(&segment.geometry))
+        } else {
+            id.clone()
+        };
+    }
+}
+
+/// `n<node id>`, else `c<hash>` of the coordinate rounded to ~10 cm.
+fn end_key(node: Option<i64>, coord: Option<&Coord<f64>>) -> String {
+    if let Some(node) = node {
+        return format!("n{node}");
+    }
+    let rounded = coord.map_or(String::new(), rounded_coord);
+    format!("c{:016x}", fnv1a(rounded.as_bytes()))
+}
+
+/// Hash of a line's coordinates rounded to ~10 cm, the same in either direction.
+fn geometry_hash(geometry: &LineString<f64>) -> u64 {
+    let forward: Vec<String> = geometry.0.iter().map(rounded_coord).collect();
+    let backward: Vec<String> = forward.iter().rev().cloned().collect();
+    fnv1a(forward.join(";").as_bytes()).min(fnv1a(backward.join(";").as_bytes()))
+}
+
+fn rounded_coord(coord: &Coord<f64>) -> String {
+    format!("{:.6},{:.6}", coord.x, coord.y)
+}
+
+/// Whether two points match, allowing for the rounding noise clipping adds (~1 cm).
+fn same_point(a: Option<&Coord<f64>>, b: Option<&Coord<f64>>) -> bool {
+    match (a, b) {
+        (Some(a), Some(b)) => (a.x - b.x).abs() < 1e-7 && (a.y - b.y).abs() < 1e-7,
+        _ => false,
+    }
+}
+
+/// Ways in an Overpass response, with their geometry inlined by `out geom`.
+fn parse_overpass_json(json: &str) -> Result<Vec<Way>> {
     let response: OverpassResponse =
         serde_json::from_str(json).context("Failed to parse Overpass JSON")?;
 
-    let ways: Vec<&OverpassElement> = response
+    Ok(response
         .elements
-        .iter()
+        .into_iter()
         .filter(|e| e.elem_type == "way")
+        .filter_map(|e| {
+            let geometry = e.geometry?;
+            let nodes = e.nodes.filter(|n| n.len() == geometry.len())?;
+            Some(Way {
+                id: e.id,
+                tags: e.tags.unwrap_or_default(),
+                nodes,
+                coords: geometry.iter().map(|p| (p.lon, p.lat)).collect(),
+            })
+        })
+        .collect())
+}
+
+/// Split the ways that pass `filter` into segments. The filter is re-applied here so a
+/// cache, a server or an extract that returns extra ways doesn't add trails.
+fn split_ways(ways: Vec<Way>, filter: &TrailFilter) -> (Vec<Trail>, Vec<Segment>) {
+    let ways: Vec<Way> = ways
+        .into_iter()
+        .filter(|way| way.coords.len() >= 2 && filter.matches(&way.tags))
         .collect();
 
     // Find shared nodes (appear in more than one way)
     let mut node_counts: HashMap<i64, u32> = HashMap::new();
     for way in &ways {
-        if let Some(nodes) = &way.nodes {
-            for node_id in nodes {
-                *node_counts.entry(*node_id).or_default() += 1;
-            }
+        for node_id in &way.nodes {
+            *node_counts.entry(*node_id).or_default() += 1;
         }
     }
     let shared_nodes: HashSet<i64> = node_counts
@@ -125,47 +626,44 @@ fn parse_overpass_json(json: &str) -> Result<(Vec<Trail>, Vec<Segment>)> {
     let mut trails = Vec::new();
     let mut segments = Vec::new();
 
-    for elem in &ways {
-        let geom = match &elem.geometry {
-            Some(g) if g.len() >= 2 => g,
-            _ => continue,
-        };
-        let nodes = match &elem.nodes {
-            Some(n) if n.len() == geom.len() => n,
-            _ => continue,
-        };
-
-        let coords: Vec<(f64, f64)> = geom.iter().map(|p| (p.lon, p.lat)).collect();
-        let name = elem.tags.as_ref().and_then(|t| t.get("name").cloned());
+    for way in &ways {
+        let (nodes, coords) = (&way.nodes, &way.coords);
+        let tags: BTreeMap<String, String> = way
+            .tags
+            .iter()
+            .filter(|(key, _)| KEPT_TAGS.contains(&key.as_str()))
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect();
 
         trails.push(Trail {
-            id: elem.id,
-            name,
+            id: way.id,
+            tags: tags.clone(),
             geometry: LineString::from(coords.clone()),
         });
 
+        let segment = |from: usize, to: usize| Segment {
+            id: String::new(),
+            way_id: way.id,
+            start_node: Some(nodes[from]),
+            end_node: Some(nodes[to]),
+            tags: tags.clone(),
+            geometry: LineString::from(coords[from..=to].to_vec()),
+        };
+
         // Split at shared nodes (excluding first and last — they're natural endpoints)
         let mut seg_start = 0;
-        for i in 1..nodes.len() - 1 {
-            if shared_nodes.contains(&nodes[i]) {
+        for (i, node) in nodes.iter().enumerate().take(nodes.len() - 1).skip(1) {
+            if shared_nodes.contains(node) {
                 // Segment from seg_start..=i
                 if i > seg_start {
-                    let seg_coords: Vec<(f64, f64)> = coords[seg_start..=i].to_vec();
-                    if seg_coords.len() >= 2 {
-                        segments.push(Segment {
-                            geometry: LineString::from(seg_coords),
-                        });
-                    }
+                    segments.push(segment(seg_start, i));
                 }
                 seg_start = i;
             }
         }
         // Final segment from seg_start to end
-        let seg_coords: Vec<(f64, f64)> = coords[seg_start..].to_vec();
-        if seg_coords.len() >= 2 {
-            segments.push(Segment {
-                geometry: LineString::from(seg_coords),
-            });
+        if nodes.len() - 1 > seg_start {
+            segments.push(segment(seg_start, nodes.len() - 1));
         }
     }
 
@@ -175,5 +673,218 @@ fn parse_overpass_json(json: &str) -> Result<(Vec<Trail>, Vec<Segment>)> {
         segments.len(),
         shared_nodes.len(),
     );
-    Ok((trails, segments))
+    (trails, segments)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_server::{MockServer, fixture};
+
+    const TRAILS_FIXTURE: &str = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/fixtures/osm/trails.json"
+    );
+
+    /// Segment ids made from an Overpass response as `fetch_trails` does, with a hash of
+    /// each segment's geometry.
+    fn ids_from(json: &str) -> BTreeMap<String, u64> {
+        let ways = parse_overpass_json(json).unwrap();
+        let (_, segments) = split_ways(ways, &TrailFilter::default());
+        let mut segments = osm_graph::normalize_segments(segments, MIN_SEGMENT_M, MERGE_SAME_TAGS);
+        assign_segment_ids(&mut segments);
+        let ids: BTreeMap<_, _> = segments
+            .iter()
+            .map(|s| (s.id.clone(), geometry_hash(&s.geometry)))
+            .collect();
+        assert_eq!(ids.len(), segments.len(), "repeated segment ids");
+        ids
+    }
+
+    /// The same response with the ways listed and drawn the other way round.
+    fn reversed(json: &str) -> String {
+        let mut response: Value = serde_json::from_str(json).unwrap();
+        let elements = response["elements"].as_array_mut().unwrap();
+        elements.reverse();
+        for way in elements {
+            for key in ["nodes", "geometry"] {
+                way[key].as_array_mut().unwrap().reverse();
+            }
+        }
+        response.to_string()
+    }
+
+    /// A fresh cache directory for one test.
+    fn cache_dir(test: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("synclinal-{test}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn write_meta(path: &Path, fetched_at: DateTime<Utc>, query: &str) {
+        let meta = CacheMeta {
+            fetched_at,
+            query: query.to_string(),
+        };
+        fs::write(path, serde_json::to_string(&meta).unwrap()).unwrap();
+    }
+
+    #[test]
+    fn dates_caches_made_by_the_same_query() {
+        let dir = cache_dir("cache-meta");
+        let (cache, meta) = (dir.join("trails.json"), dir.join("trails.meta.json"));
+        fs::write(&cache, "{}").unwrap();
+        let fetched_at: DateTime<Utc> = "2026-06-01T12:00:00Z".parse().unwrap();
+
+        // Without metadata, the file's own date
+        let modified = cache_fetched_at(&cache, &meta, "query").unwrap();
+        assert!(Utc::now() - modified < chrono::Duration::minutes(1));
+
+        write_meta(&meta, fetched_at, "query");
+        assert_eq!(cache_fetched_at(&cache, &meta, "query"), Some(fetched_at));
+        assert_eq!(cache_fetched_at(&cache, &meta, "other query"), None);
+
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(cache_fetched_at(&cache, &meta, "query"), None);
+    }
+
+    #[tokio::test]
+    async fn refetches_a_cache_made_by_another_query() {
+        let json = fs::read_to_string(TRAILS_FIXTURE).unwrap();
+        let server = MockServer::start(|_| (200, fixture("osm/trails.json"))).await;
+        let dir = cache_dir("osm-refetch");
+        let region: Region = toml::from_str(&format!(
+            "title = \"Saou\"\nbounds = {{ south = 44.6, west = 5.0, north = 44.7, east = 5.2 }}\ncache_dir = \"{}\"\noverpass_urls = [\"{}/api/interpreter\"]",
+            dir.display(),
+            server.url
+        ))
+        .unwrap();
+        let area = Area {
+            bounds: region.bounds.unwrap(),
+            boundary: None,
+        };
+        let query = region.trail_filter().overpass_query(&area.bounds);
+        let cache = cache_path(&region);
+        let meta = cache.with_extension("meta.json");
+
+        // A fresh cache of the same query is used as it is
+        write_meta(&meta, Utc::now(), &query);
+        fs::write(&cache, "cached").unwrap();
+        let client = reqwest::Client::new();
+        assert_eq!(
+            overpass_trails(&client, &region, &area).await.unwrap(),
+            "cached"
+        );
+        assert!(server.paths().is_empty());
+
+        // One made by another query (e.g. before the bounds changed) is fetched again
+        let mut old: Value = serde_json::from_str(&json).unwrap();
+        old["elements"]
+            .as_array_mut()
+            .unwrap()
+            .retain(|e| e["id"] != 22);
+        fs::write(&cache, old.to_string()).unwrap();
+        write_meta(&meta, Utc::now(), "[out:json];way[highway=path];out geom;");
+
+        assert_eq!(
+            overpass_trails(&client, &region, &area).await.unwrap(),
+            json
+        );
+        assert_eq!(server.paths(), ["/api/interpreter"]);
+        assert_eq!(fs::read_to_string(&cache).unwrap(), json);
+        assert!(cache_fetched_at(&cache, &meta, &query).is_some());
+
+        let changes: Value =
+            serde_json::from_str(&fs::read_to_string(dir.join(OSM_CHANGES_FILENAME)).unwrap())
+                .unwrap();
+        assert_eq!(changes["added"][0]["id"], 22);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn retries_broken_overpass_responses() {
+        let transient =
+            |body: &str| matches!(validate_response(body), Err(OverpassError::Transient(_)));
+        assert!(transient(
+            "<?xml version=\"1.0\"?>\n<html><body><p>The server is probably too busy to handle your request.</p></body></html>"
+        ));
+        assert!(transient(
+            r#"{"version": 0.6, "elements": [], "remark": "runtime error: Query timed out in \"query\" at line 3 after 61 seconds."}"#
+        ));
+        assert!(transient(
+            r#"{"version": 0.6, "elements": [{"type": "way", "id": 1, "nodes": [1, 2"#
+        ));
+        assert!(transient(r#"{"version": 0.6}"#));
+    }
+
+    #[test]
+    fn accepts_only_non_empty_results() {
+        assert!(matches!(
+            validate_response(r#"{"version": 0.6, "elements": []}"#),
+            Err(OverpassError::Fatal(_))
+        ));
+        let json = fs::read_to_string(TRAILS_FIXTURE).unwrap();
+        assert!(validate_response(&json).is_ok());
+    }
+
+    #[test]
+    fn keeps_ids_when_fetched_again() {
+        let json = fs::read_to_string(TRAILS_FIXTURE).unwrap();
+        let ids = ids_from(&json);
+        assert!(ids.contains_key("w11:n1001-n1101"));
+        // Way 20 runs from node 2000 to 2002 and back along another side
+        assert_eq!(
+            ids.keys()
+                .filter(|id| id.starts_with("w20:n2000-n2002~"))
+                .count(),
+            2
+        );
+
+        for _ in 0..5 {
+            assert_eq!(ids_from(&json), ids);
+        }
+        assert_eq!(ids_from(&reversed(&json)), ids);
+    }
+
+    #[test]
+    fn repeated_ids_do_not_depend_on_order() {
+        let north = [(5.0, 44.7), (5.001, 44.701), (5.002, 44.7)];
+        let south = [(5.002, 44.7), (5.001, 44.699), (5.0, 44.7)];
+        let mut segments = vec![
+            Segment::path(20, Some(1), Some(2), &north),
+            Segment::path(20, Some(2), Some(1), &south),
+        ];
+        assign_segment_ids(&mut segments);
+        let (north_id, south_id) = (segments[0].id.clone(), segments[1].id.clone());
+        assert_ne!(north_id, south_id);
+        assert!(north_id.starts_with("w20:n1-n2~"));
+
+        let mut segments = vec![
+            Segment::path(20, Some(2), Some(1), &south).reversed(),
+            Segment::path(20, Some(1), Some(2), &north).reversed(),
+        ];
+        assign_segment_ids(&mut segments);
+        assert_eq!(segments[0].id, south_id);
+        assert_eq!(segments[1].id, north_id);
+    }
+
+    #[test]
+    fn hashes_boundary_cuts_in_full() {
+        let coords = [(5.0, 44.7), (5.00123, 44.70045)];
+        let mut segments = vec![Segment::path(7, Some(1), None, &coords)];
+        assign_segment_ids(&mut segments);
+        let id = segments[0].id.clone();
+        let hash = id
+            .strip_prefix("w7:c")
+            .unwrap()
+            .strip_suffix("-n1")
+            .unwrap();
+        assert_eq!(hash.len(), 16);
+        assert!(hash.chars().all(|c| c.is_ascii_hexdigit()));
+
+        let mut reversed = vec![Segment::path(7, None, Some(1), &[coords[1], coords[0]])];
+        assign_segment_ids(&mut reversed);
+        assert_eq!(reversed[0].id, id);
+    }
 }
@@ -0,0 +1,189 @@
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use crate::geo_util::haversine_m;
+use crate::osm::Way;
+
+/// Ways listed per kind in the printed report; the JSON report has all of them.
+const MAX_LISTED: usize = 15;
+
+/// Trail ways that differ between two fetches of the same query.
+#[derive(Serialize)]
+pub struct WayDiff {
+    pub previous_fetch: Option<String>,
+    pub fetched_at: String,
+    pub added: Vec<WayChange>,
+    pub removed: Vec<WayChange>,
+    /// Same way id, different nodes or node positions.
+    pub changed: Vec<WayChange>,
+}
+
+#[derive(Serialize)]
+pub struct WayChange {
+    pub id: i64,
+    pub name: Option<String>,
+    pub highway: Option<String>,
+    pub length_m: f64,
+}
+
+impl WayDiff {
+    pub fn new(
+        old: &[Way],
+        new: &[Way],
+        previous_fetch: Option<String>,
+        fetched_at: String,
+    ) -> Self {
+        let old_by_id: HashMap<i64, &Way> = old.iter().map(|w| (w.id, w)).collect();
+        let new_by_id: HashMap<i64, &Way> = new.iter().map(|w| (w.id, w)).collect();
+
+        let mut added = Vec::new();
+        let mut changed = Vec::new();
+        for way in new {
+            match old_by_id.get(&way.id) {
+                None => added.push(WayChange::of(way)),
+                Some(old) if old.nodes != way.nodes || old.coords != way.coords => {
+                    changed.push(WayChange::of(way))
+                }
+                Some(_) => {}
+            }
+        }
+        let removed = old
+            .iter()
+            .filter(|w| !new_by_id.contains_key(&w.id))
+            .map(WayChange::of)
+            .collect();
+
+        Self {
+            previous_fetch,
+            fetched_at,
+            added,
+            removed,
+            changed,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+
+    pub fn print(&self) {
+        let km = |ways: &[WayChange]| ways.iter().map(|w| w.length_m).sum::<f64>() / 1000.0;
+        eprintln!(
+            "OSM changes since {}: {} ways added ({:.1} km), {} removed ({:.1} km), {} re-geometried",
+            self.previous_fetch
+                .as_deref()
+                .unwrap_or("the previous fetch"),
+            self.added.len(),
+            km(&self.added),
+            self.removed.len(),
+            km(&self.removed),
+            self.changed.len(),
+        );
+        for (sign, ways) in [
+            ("+", &self.added),
+            ("-", &self.removed),
+            ("~", &self.changed),
+        ] {
+            for way in ways.iter().take(MAX_LISTED) {
+                eprintln!(
+                    "  {sign} way {} {}({}, {:.0} m)",
+                    way.id,
+                    way.name
+                        .as_deref()
+                        .map(|n| format!("{n} "))
+                        .unwrap_or_default(),
+                    way.highway.as_deref().unwrap_or("?"),
+                    way.length_m,
+                );
+            }
+            if ways.len() > MAX_LISTED {
+                eprintln!("  {sign} ... and {} more", ways.len() - MAX_LISTED);
+            }
+        }
+    }
+
+    pub fn write(&self, path: &Path) -> Result<()> {
+        fs::write(path, serde_json::to_string_pretty(self)?)
+            .with_context(|| format!("Failed to write {}", path.display()))
+    }
+}
+
+impl WayChange {
+    fn of(way: &Way) -> Self {
+        let length_m = way
+            .coords
+            .windows(2)
+            .map(|w| haversine_m(w[0].1, w[0].0, w[1].1, w[1].0))
+            .sum::<f64>();
+        Self {
+            id: way.id,
+            name: way.tags.get("name").cloned(),
+            highway: way.tags.get("highway").cloned(),
+            length_m: (length_m * 10.0).round() / 10.0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn way(id: i64, nodes: &[i64], name: Option<&str>) -> Way {
+        let mut tags = HashMap::from([("highway".to_string(), "path".to_string())]);
+        if let Some(name) = name {
+            tags.insert("name".to_string(), name.to_string());
+        }
+        Way {
+            id,
+            tags,
+            nodes: nodes.to_vec(),
+            coords: nodes
+                .iter()
+                .map(|&n| (5.1 + n as f64 * 0.001, 44.65))
+                .collect(),
+        }
+    }
+
+    fn ids(changes: &[WayChange]) -> Vec<i64> {
+        changes.iter().map(|c| c.id).collect()
+    }
+
+    #[test]
+    fn finds_added_removed_and_changed_ways() {
+        let old = vec![
+            way(1, &[1, 2], None),
+            way(2, &[2, 3], None),
+            way(3, &[3, 4], None),
+            way(4, &[4, 5], None),
+        ];
+        let mut moved = way(3, &[3, 4], None);
+        moved.coords[1].1 += 0.0001;
+        let new = vec![
+            way(1, &[1, 2], Some("Renamed only")),
+            way(2, &[2, 6, 3], None),
+            moved,
+            way(5, &[5, 6], Some("Pas de l'Echelle")),
+        ];
+
+        let diff = WayDiff::new(&old, &new, None, "2026-06-30".to_string());
+        assert_eq!(ids(&diff.added), [5]);
+        assert_eq!(ids(&diff.removed), [4]);
+        assert_eq!(ids(&diff.changed), [2, 3]);
+        assert!(!diff.is_empty());
+
+        let added = &diff.added[0];
+        assert_eq!(added.name.as_deref(), Some("Pas de l'Echelle"));
+        assert_eq!(added.highway.as_deref(), Some("path"));
+        assert_eq!(added.length_m, 79.1);
+    }
+
+    #[test]
+    fn same_ways_make_no_changes() {
+        let ways = vec![way(1, &[1, 2], None), way(2, &[2, 3], None)];
+        let reordered = vec![way(2, &[2, 3], None), way(1, &[1, 2], None)];
+        assert!(WayDiff::new(&ways, &reordered, None, String::new()).is_empty());
+    }
+}
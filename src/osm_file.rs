@@ -0,0 +1,215 @@
+use anyhow::{Context, Result, bail};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+
+use crate::osm::Way;
+use crate::region::Bounds;
+use crate::trail_filter::TrailFilter;
+
+/// Ways passing `filter` with at least one node inside `bounds`, read from an OSM extract
+/// (`.osm.pbf`, or `.osm` XML). Like Overpass, ways crossing the bounds are kept whole.
+pub fn read_ways(path: &Path, filter: &TrailFilter, bounds: &Bounds) -> Result<Vec<Way>> {
+    let name = path.to_string_lossy().to_lowercase();
+    let (ways, locations) = if name.ends_with(".pbf") {
+        read_pbf(path, filter)?
+    } else if name.ends_with(".osm") || name.ends_with(".xml") {
+        read_xml(path, filter)?
+    } else {
+        bail!(
+            "Unknown OSM extract format for {} (expected .osm.pbf or .osm)",
+            path.display()
+        );
+    };
+
+    let total = ways.len();
+    let (ways, incomplete) = resolve_ways(ways, &locations, bounds);
+    eprintln!(
+        "Found {total} trail ways in {}, {} in the area",
+        path.display(),
+        ways.len()
+    );
+    if incomplete > 0 {
+        eprintln!("Warning: skipped {incomplete} ways with nodes missing from the extract");
+    }
+    Ok(ways)
+}
+
+/// Ways with their node locations that have a node inside `bounds`, and how many ways
+/// were skipped because some of their nodes are missing.
+fn resolve_ways(ways: Vec<RawWay>, locations: &Locations, bounds: &Bounds) -> (Vec<Way>, usize) {
+    let mut incomplete = 0;
+    let ways = ways
+        .into_iter()
+        .filter_map(|(id, tags, nodes)| {
+            // Drop ways cut by the extract's edge rather than keep a partial geometry
+            let coords: Option<Vec<(f64, f64)>> =
+                nodes.iter().map(|n| locations.get(n).copied()).collect();
+            let Some(coords) = coords else {
+                incomplete += 1;
+                return None;
+            };
+            coords
+                .iter()
+                .any(|&(lon, lat)| bounds.contains(lat, lon))
+                .then_some(Way {
+                    id,
+                    tags,
+                    nodes,
+                    coords,
+                })
+        })
+        .collect();
+    (ways, incomplete)
+}
+
+/// Id, tags and node ids of a way, before its node locations are known.
+type RawWay = (i64, HashMap<String, String>, Vec<i64>);
+/// (lon, lat) by node id.
+type Locations = HashMap<i64, (f64, f64)>;
+
+/// Node locations are only known after the ways, so the file is read twice: once for the
+/// matching ways, then for the locations of their nodes.
+fn read_pbf(path: &Path, filter: &TrailFilter) -> Result<(Vec<RawWay>, Locations)> {
+    let open = || {
+        osmpbf::ElementReader::from_path(path)
+            .with_context(|| format!("Failed to open {}", path.display()))
+    };
+    let failed = || format!("Failed to read {}", path.display());
+
+    let mut ways = Vec::new();
+    open()?
+        .for_each(|element| {
+            if let osmpbf::Element::Way(way) = element {
+                let tags: HashMap<String, String> = way
+                    .tags()
+                    .map(|(k, v)| (k.to_string(), v.to_string()))
+                    .collect();
+                if filter.matches(&tags) {
+                    ways.push((way.id(), tags, way.refs().collect()));
+                }
+            }
+        })
+        .with_context(failed)?;
+
+    let wanted: HashSet<i64> = ways
+        .iter()
+        .flat_map(|(_, _, nodes)| nodes)
+        .copied()
+        .collect();
+    let mut locations = HashMap::with_capacity(wanted.len());
+    open()?
+        .for_each(|element| {
+            let (id, lon, lat) = match element {
+                osmpbf::Element::Node(node) => (node.id(), node.lon(), node.lat()),
+                osmpbf::Element::DenseNode(node) => (node.id(), node.lon(), node.lat()),
+                _ => return,
+            };
+            if wanted.contains(&id) {
+                locations.insert(id, (lon, lat));
+            }
+        })
+        .with_context(failed)?;
+    Ok((ways, locations))
+}
+
+fn read_xml(path: &Path, filter: &TrailFilter) -> Result<(Vec<RawWay>, Locations)> {
+    let text =
+        fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    let doc = roxmltree::Document::parse(&text)
+        .with_context(|| format!("Failed to parse {} as OSM XML", path.display()))?;
+    let id_of = |node: roxmltree::Node, attr: &str| node.attribute(attr)?.parse::<i64>().ok();
+
+    let mut ways = Vec::new();
+    for way in doc.descendants().filter(|n| n.has_tag_name("way")) {
+        let Some(id) = id_of(way, "id") else {
+            continue;
+        };
+        let children = || way.children().filter(|n| n.is_element());
+        let tags: HashMap<String, String> = children()
+            .filter(|n| n.has_tag_name("tag"))
+            .filter_map(|n| Some((n.attribute("k")?.to_string(), n.attribute("v")?.to_string())))
+            .collect();
+        if filter.matches(&tags) {
+            let nodes = children()
+                .filter(|n| n.has_tag_name("nd"))
+                .filter_map(|n| id_of(n, "ref"))
+                .collect();
+            ways.push((id, tags, nodes));
+        }
+    }
+
+    let wanted: HashSet<i64> = ways
+        .iter()
+        .flat_map(|(_, _, nodes)| nodes)
+        .copied()
+        .collect();
+    let locations = doc
+        .descendants()
+        .filter(|n| n.has_tag_name("node"))
+        .filter_map(|n| {
+            let id = id_of(n, "id").filter(|id| wanted.contains(id))?;
+            let lat = n.attribute("lat")?.parse().ok()?;
+            let lon = n.attribute("lon")?.parse().ok()?;
+            Some((id, (lon, lat)))
+        })
+        .collect();
+    Ok((ways, locations))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXTRACT: &str = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/fixtures/osm/extract.osm"
+    );
+
+    fn saou() -> Bounds {
+        Bounds {
+            south: 44.6,
+            west: 5.0,
+            north: 44.7,
+            east: 5.2,
+        }
+    }
+
+    #[test]
+    fn reads_trail_ways_in_the_area() {
+        let ways = read_ways(Path::new(EXTRACT), &TrailFilter::default(), &saou()).unwrap();
+        // A road and a private path are filtered out, a path outside the area and one with
+        // a missing node are dropped; the track leaving the area is kept whole
+        let ids: Vec<i64> = ways.iter().map(|w| w.id).collect();
+        assert_eq!(ids, [100, 101]);
+
+        assert_eq!(ways[0].nodes, [1, 2, 3]);
+        assert_eq!(ways[0].coords[1], (5.101, 44.651));
+        assert_eq!(ways[0].tags["sac_scale"], "mountain_hiking");
+        assert_eq!(ways[1].coords, [(5.103, 44.652), (5.25, 44.75)]);
+    }
+
+    #[test]
+    fn counts_ways_with_missing_nodes() {
+        let (raw, locations) = read_xml(Path::new(EXTRACT), &TrailFilter::default()).unwrap();
+        let raw_ids: Vec<i64> = raw.iter().map(|(id, _, _)| *id).collect();
+        assert_eq!(raw_ids, [100, 101, 104, 105]);
+        // Only the nodes of matching ways are kept
+        assert!(!locations.contains_key(&20));
+
+        let (ways, incomplete) = resolve_ways(raw, &locations, &saou());
+        assert_eq!(ways.len(), 2);
+        assert_eq!(incomplete, 1);
+    }
+
+    #[test]
+    fn rejects_unknown_extract_formats() {
+        let result = read_ways(
+            Path::new("trails.geojson"),
+            &TrailFilter::default(),
+            &saou(),
+        );
+        let error = result.err().unwrap().to_string();
+        assert!(error.starts_with("Unknown OSM extract format"), "{error}");
+    }
+}
@@ -0,0 +1,673 @@
+use geo_types::Coord;
+use std::collections::{BTreeMap, HashMap};
+
+use crate::geo_util::linestring_length_m;
+use crate::osm::Segment;
+
+/// How many dead ends the summary lists, so they can be checked on a map.
+const DEAD_ENDS_LISTED: usize = 10;
+
+/// The trail network: junctions and trail ends as nodes, segments as edges.
+///
+/// Nodes and edges are referred to by their index. Edge `i` is `segments[i]` of the
+/// segments the graph was built from.
+pub struct TrailGraph {
+    pub nodes: Vec<GraphNode>,
+    pub edges: Vec<GraphEdge>,
+    /// Edges at each node; a loop is listed twice.
+    adjacency: Vec<Vec<usize>>,
+    by_osm_id: HashMap<i64, usize>,
+}
+
+pub struct GraphNode {
+    /// `None` for a segment end where the boundary cut a way between two OSM nodes.
+    pub osm_id: Option<i64>,
+    /// (lon, lat)
+    pub coord: Coord<f64>,
+}
+
+pub struct GraphEdge {
+    pub from: usize,
+    pub to: usize,
+    pub way_id: i64,
+    pub length_m: f64,
+    pub tags: BTreeMap<String, String>,
+}
+
+impl TrailGraph {
+    pub fn new(segments: &[Segment]) -> Self {
+        let mut graph = Self {
+            nodes: Vec::new(),
+            edges: Vec::with_capacity(segments.len()),
+            adjacency: Vec::new(),
+            by_osm_id: HashMap::new(),
+        };
+        for segment in segments {
+            let points = &segment.geometry.0;
+            let (Some(&first), Some(&last)) = (points.first(), points.last()) else {
+                continue;
+            };
+            let from = graph.add_node(segment.start_node, first);
+            let to = graph.add_node(segment.end_node, last);
+            let edge = graph.edges.len();
+            graph.edges.push(GraphEdge {
+                from,
+                to,
+                way_id: segment.way_id,
+                length_m: linestring_length_m(&segment.geometry),
+                tags: segment.tags.clone(),
+            });
+            graph.adjacency[from].push(edge);
+            graph.adjacency[to].push(edge);
+        }
+        graph
+    }
+
+    /// Node for an OSM node id, reusing the existing one. Ends without an id always get
+    /// their own node.
+    fn add_node(&mut self, osm_id: Option<i64>, coord: Coord<f64>) -> usize {
+        if let Some(node) = osm_id.and_then(|id| self.node_by_osm_id(id)) {
+            return node;
+        }
+        let node = self.nodes.len();
+        self.nodes.push(GraphNode { osm_id, coord });
+        self.adjacency.push(Vec::new());
+        if let Some(id) = osm_id {
+            self.by_osm_id.insert(id, node);
+        }
+        node
+    }
+
+    pub fn node_by_osm_id(&self, osm_id: i64) -> Option<usize> {
+        self.by_osm_id.get(&osm_id).copied()
+    }
+
+    /// Number of segment ends at a node (a loop counts twice).
+    pub fn degree(&self, node: usize) -> usize {
+        self.edges_at(node).len()
+    }
+
+    /// Edges at a node.
+    pub fn edges_at(&self, node: usize) -> &[usize] {
+        &self.adjacency[node]
+    }
+
+    /// The node at the other end of `edge` from `node`.
+    pub fn opposite(&self, edge: usize, node: usize) -> usize {
+        let edge = &self.edges[edge];
+        if edge.from == node {
+            edge.to
+        } else {
+            edge.from
+        }
+    }
+
+    /// A trail end that joins nothing, as opposed to one cut by the boundary.
+    pub fn is_dead_end(&self, node: usize) -> bool {
+        self.degree(node) == 1 && self.nodes[node].osm_id.is_some()
+    }
+
+    /// Connected components as lists of edges, longest total length first.
+    pub fn components(&self) -> Vec<Vec<usize>> {
+        let mut component_of = vec![usize::MAX; self.nodes.len()];
+        let mut count = 0;
+        for start in 0..self.nodes.len() {
+            if component_of[start] != usize::MAX {
+                continue;
+            }
+            component_of[start] = count;
+            let mut stack = vec![start];
+            while let Some(node) = stack.pop() {
+                for &edge in self.edges_at(node) {
+                    let next = self.opposite(edge, node);
+                    if component_of[next] == usize::MAX {
+                        component_of[next] = count;
+                        stack.push(next);
+                    }
+                }
+            }
+            count += 1;
+        }
+
+        let mut components = vec![Vec::new(); count];
+        for (index, edge) in self.edges.iter().enumerate() {
+            components[component_of[edge.from]].push(index);
+        }
+        components.sort_by(|a, b| self.length_m(b).total_cmp(&self.length_m(a)));
+        components
+    }
+
+    /// Total length of some edges.
+    pub fn length_m(&self, edges: &[usize]) -> f64 {
+        edges.iter().map(|&edge| self.edges[edge].length_m).sum()
+    }
+
+    pub fn print_summary(&self) {
+        let components = self.components();
+        let total_m = self.length_m(&(0..self.edges.len()).collect::<Vec<_>>());
+        let largest_pct = match components.first() {
+            Some(largest) if total_m > 0.0 => self.length_m(largest) / total_m * 100.0,
+            _ => 0.0,
+        };
+        let dead_ends: Vec<usize> = (0..self.nodes.len())
+            .filter(|&node| self.is_dead_end(node))
+            .collect();
+        eprintln!(
+            "Trail network: {} nodes, {} edges, {} connected components (largest {:.0}% of the length), {} dead ends",
+            self.nodes.len(),
+            self.edges.len(),
+            components.len(),
+            largest_pct,
+            dead_ends.len(),
+        );
+        for &node in dead_ends.iter().take(DEAD_ENDS_LISTED) {
+            eprintln!("  dead end: {}", self.describe_dead_end(node));
+        }
+        if dead_ends.len() > DEAD_ENDS_LISTED {
+            eprintln!("  ... and {} more", dead_ends.len() - DEAD_ENDS_LISTED);
+        }
+    }
+
+    /// Where a dead end is and which way leads to it, e.g.
+    /// `node 45 at 44.70100,5.00000 on way 123 "Sentier des Crêtes"`.
+    fn describe_dead_end(&self, node: usize) -> String {
+        let GraphNode { osm_id, coord } = &self.nodes[node];
+        let mut text = format!(
+            "node {} at {:.5},{:.5}",
+            osm_id.unwrap_or_default(),
+            coord.y,
+            coord.x
+        );
+        if let Some(&edge) = self.edges_at(node).first() {
+            let edge = &self.edges[edge];
+            text += &format!(" on way {}", edge.way_id);
+            if let Some(name) = edge.tags.get("name") {
+                text += &format!(" \"{name}\"");
+            }
+        }
+        text
+    }
+}
+
+/// Turn segments split at every shared node into junction-to-junction pieces: join
+/// segments meeting at a node where only their two ends meet (if `same_tags`, only when
+/// their tags match), and drop or contract segments shorter than `min_length_m`.
+pub fn normalize_segments(
+    segments: Vec<Segment>,
+    min_length_m: f64,
+    same_tags: bool,
+) -> Vec<Segment> {
+    let before = segments.len();
+    let pieces = segments.into_iter().map(Piece::from).collect();
+    let (pieces, joined) = merge_through_junctions(pieces, same_tags);
+    let (pieces, dropped, contracted) = remove_slivers(pieces, min_length_m);
+    // Dropping a sliver can leave a junction of two behind
+    let (pieces, joined_after) = merge_through_junctions(pieces, same_tags);
+    eprintln!(
+        "Normalized {before} segments into {}: {} joined through junctions, {dropped} slivers dropped, {contracted} contracted",
+        pieces.len(),
+        joined + joined_after,
+    );
+    pieces.into_iter().map(|piece| piece.segment).collect()
+}
+
+/// A segment being normalized, with the length of the longest original segment joined
+/// into it, which gave it its way id and tags.
+struct Piece {
+    segment: Segment,
+    way_m: f64,
+}
+
+impl From<Segment> for Piece {
+    fn from(segment: Segment) -> Self {
+        let way_m = linestring_length_m(&segment.geometry);
+        Self { segment, way_m }
+    }
+}
+
+/// Join the two pieces at each node where exactly two segment ends meet. Returns the
+/// pieces left and the number of joins.
+fn merge_through_junctions(pieces: Vec<Piece>, same_tags: bool) -> (Vec<Piece>, usize) {
+    let mut ends = segment_ends(&pieces);
+    let mut pieces: Vec<Option<Piece>> = pieces.into_iter().map(Some).collect();
+
+    let mut junctions: Vec<i64> = ends
+        .iter()
+        .filter(|(_, at)| at.len() == 2)
+        .map(|(&node, _)| node)
+        .collect();
+    junctions.sort_unstable();
+
+    let mut joins = 0;
+    for node in junctions {
+        let &[a, b] = ends[&node].as_slice() else {
+            continue;
+        };
+        // A loop closing on itself
+        if a == b {
+            continue;
+        }
+        let (Some(first), Some(second)) = (&pieces[a], &pieces[b]) else {
+            continue;
+        };
+        if same_tags && first.segment.tags != second.segment.tags {
+            continue;
+        }
+        let (Some(first), Some(second)) = (pieces[a].take(), pieces[b].take()) else {
+            continue;
+        };
+        let joined = join(first, second, node);
+        // The joined piece keeps index `a`; the far end of `b` now leads to it
+        if let Some(at) = joined.segment.end_node.and_then(|far| ends.get_mut(&far)) {
+            for index in at.iter_mut().filter(|index| **index == b) {
+                *index = a;
+            }
+        }
+        ends.remove(&node);
+        pieces[a] = Some(joined);
+        joins += 1;
+    }
+    (pieces.into_iter().flatten().collect(), joins)
+}
+
+/// Join two pieces sharing `node` into one running through it. The longest original
+/// segment in either (or the lower way id, if they are as long) gives the way id and
+/// tags, whichever order the joins happen in.
+fn join(first: Piece, second: Piece, node: i64) -> Piece {
+    let way_m = first.way_m.max(second.way_m);
+    let first_gives_way = first
+        .way_m
+        .total_cmp(&second.way_m)
+        .then(second.segment.way_id.cmp(&first.segment.way_id))
+        .is_ge();
+    let first = if first.segment.end_node == Some(node) {
+        first.segment
+    } else {
+        first.segment.reversed()
+    };
+    let second = if second.segment.start_node == Some(node) {
+        second.segment
+    } else {
+        second.segment.reversed()
+    };
+    let (way_id, tags) = if first_gives_way {
+        (first.way_id, first.tags)
+    } else {
+        (second.way_id, second.tags)
+    };
+    let mut points = first.geometry.0;
+    points.extend(second.geometry.0.into_iter().skip(1));
+    let segment = Segment {
+        id: String::new(),
+        way_id,
+        start_node: first.start_node,
+        end_node: second.end_node,
+        tags,
+        geometry: points.into(),
+    };
+    Piece { segment, way_m }
+}
+
+/// Drop slivers hanging off the network (at a dead end, a boundary cut or in a loop),
+/// and contract the others by moving the ends at one of their nodes to the other, the
+/// busier junction or else the lower node id. Slivers go shortest first, so the result
+/// doesn't depend on the order or direction of the segments. Returns the segments left
+/// and how many were dropped and contracted.
+fn remove_slivers(mut pieces: Vec<Piece>, min_length_m: f64) -> (Vec<Piece>, usize, usize) {
+    let (mut dropped, mut contracted) = (0, 0);
+    while let Some((index, _)) = pieces
+        .iter()
+        .map(|piece| linestring_length_m(&piece.segment.geometry))
+        .enumerate()
+        .filter(|&(_, length)| length < min_length_m)
+        .min_by(|(_, a), (_, b)| a.total_cmp(b))
+    {
+        let ends = segment_ends(&pieces);
+        let sliver = pieces.swap_remove(index).segment;
+        let degree = |node: i64| ends.get(&node).map_or(0, Vec::len);
+        let (keep, gone) = match (sliver.start_node, sliver.end_node) {
+            (Some(a), Some(b)) if a != b && degree(a) > 1 && degree(b) > 1 => {
+                if (degree(a), b) > (degree(b), a) {
+                    (a, b)
+                } else {
+                    (b, a)
+                }
+            }
+            _ => {
+                dropped += 1;
+                continue;
+            }
+        };
+        let Some(&coord) = (if sliver.start_node == Some(keep) {
+            sliver.geometry.0.first()
+        } else {
+            sliver.geometry.0.last()
+        }) else {
+            continue;
+        };
+        for segment in pieces.iter_mut().map(|piece| &mut piece.segment) {
+            if segment.start_node == Some(gone) {
+                segment.start_node = Some(keep);
+                segment.geometry.0[0] = coord;
+            }
+            if segment.end_node == Some(gone) {
+                segment.end_node = Some(keep);
+                if let Some(last) = segment.geometry.0.last_mut() {
+                    *last = coord;
+                }
+            }
+        }
+        contracted += 1;
+    }
+    (pieces, dropped, contracted)
+}
+
+/// Pieces with a segment end at each OSM node; a loop is listed twice.
+fn segment_ends(pieces: &[Piece]) -> HashMap<i64, Vec<usize>> {
+    let mut ends: HashMap<i64, Vec<usize>> = HashMap::new();
+    for (index, Piece { segment, .. }) in pieces.iter().enumerate() {
+        for node in [segment.start_node, segment.end_node].into_iter().flatten() {
+            ends.entry(node).or_default().push(index);
+        }
+    }
+    ends
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Three ways meeting at node 1, one of them going on to a boundary cut, and apart
+    /// from them a way ending in a loop.
+    fn network() -> Vec<Segment> {
+        vec![
+            Segment::path(1, Some(1), Some(2), &[(5.0, 44.7), (5.0, 44.701)]),
+            Segment::path(2, Some(1), Some(3), &[(5.0, 44.7), (5.001, 44.7)]),
+            Segment::path(3, Some(4), Some(1), &[(4.999, 44.7), (5.0, 44.7)]),
+            Segment::path(4, Some(4), None, &[(4.999, 44.7), (4.998, 44.7)]),
+            Segment::path(5, Some(10), Some(11), &[(5.01, 44.71), (5.01, 44.711)]),
+            Segment::path(
+                6,
+                Some(11),
+                Some(11),
+                &[
+                    (5.01, 44.711),
+                    (5.0105, 44.711),
+                    (5.0105, 44.7115),
+                    (5.01, 44.711),
+                ],
+            ),
+        ]
+    }
+
+    #[test]
+    fn shares_nodes_between_segments() {
+        let graph = TrailGraph::new(&network());
+        // Nodes 1, 2, 3, 4, 10, 11 and the boundary cut
+        assert_eq!(graph.nodes.len(), 7);
+        assert_eq!(graph.edges.len(), 6);
+
+        let junction = graph.node_by_osm_id(1).unwrap();
+        assert_eq!(graph.nodes[junction].coord, Coord { x: 5.0, y: 44.7 });
+        assert_eq!(graph.node_by_osm_id(99), None);
+
+        let cut = graph.edges[3].to;
+        assert_eq!(graph.nodes[cut].osm_id, None);
+        assert_eq!(graph.edges[3].way_id, 4);
+        assert_eq!(graph.edges[3].tags["highway"], "path");
+    }
+
+    #[test]
+    fn walks_edges_at_a_junction() {
+        let graph = TrailGraph::new(&network());
+        let junction = graph.node_by_osm_id(1).unwrap();
+        assert_eq!(graph.degree(junction), 3);
+        assert_eq!(graph.edges_at(junction), [0, 1, 2]);
+
+        let neighbours: Vec<_> = graph
+            .edges_at(junction)
+            .iter()
+            .map(|&edge| graph.nodes[graph.opposite(edge, junction)].osm_id)
+            .collect();
+        assert_eq!(neighbours, [Some(2), Some(3), Some(4)]);
+    }
+
+    #[test]
+    fn counts_a_loop_twice() {
+        let graph = TrailGraph::new(&network());
+        let node = graph.node_by_osm_id(11).unwrap();
+        assert_eq!(graph.degree(node), 3);
+        assert_eq!(graph.edges_at(node), [4, 5, 5]);
+        assert_eq!(graph.opposite(5, node), node);
+    }
+
+    #[test]
+    fn finds_components_longest_first() {
+        let graph = TrailGraph::new(&network());
+        let components = graph.components();
+        assert_eq!(components, [vec![0, 1, 2, 3], vec![4, 5]]);
+        assert!(graph.length_m(&components[0]) > graph.length_m(&components[1]));
+    }
+
+    #[test]
+    fn boundary_cuts_are_not_dead_ends() {
+        let graph = TrailGraph::new(&network());
+        let dead_ends: Vec<_> = (0..graph.nodes.len())
+            .filter(|&node| graph.is_dead_end(node))
+            .map(|node| graph.nodes[node].osm_id)
+            .collect();
+        assert_eq!(dead_ends, [Some(2), Some(3), Some(10)]);
+
+        let cut = graph.edges[3].to;
+        assert_eq!(graph.degree(cut), 1);
+        assert!(!graph.is_dead_end(cut));
+    }
+
+    #[test]
+    fn describes_dead_ends() {
+        let mut segments = network();
+        segments[0]
+            .tags
+            .insert("name".to_string(), "Sentier des Crêtes".to_string());
+        let graph = TrailGraph::new(&segments);
+        let node = |osm_id| graph.node_by_osm_id(osm_id).unwrap();
+        assert_eq!(
+            graph.describe_dead_end(node(2)),
+            "node 2 at 44.70100,5.00000 on way 1 \"Sentier des Crêtes\""
+        );
+        assert_eq!(
+            graph.describe_dead_end(node(10)),
+            "node 10 at 44.71000,5.01000 on way 5"
+        );
+    }
+
+    /// Run `merge_through_junctions` on plain segments.
+    fn merge(segments: Vec<Segment>, same_tags: bool) -> (Vec<Segment>, usize) {
+        let pieces = segments.into_iter().map(Piece::from).collect();
+        let (pieces, joins) = merge_through_junctions(pieces, same_tags);
+        let segments = pieces.into_iter().map(|piece| piece.segment).collect();
+        (segments, joins)
+    }
+
+    /// Run `remove_slivers` on plain segments.
+    fn remove(segments: Vec<Segment>, min_length_m: f64) -> (Vec<Segment>, usize, usize) {
+        let pieces = segments.into_iter().map(Piece::from).collect();
+        let (pieces, dropped, contracted) = remove_slivers(pieces, min_length_m);
+        let segments = pieces.into_iter().map(|piece| piece.segment).collect();
+        (segments, dropped, contracted)
+    }
+
+    fn tagged(mut segment: Segment, highway: &str) -> Segment {
+        segment
+            .tags
+            .insert("highway".to_string(), highway.to_string());
+        segment
+    }
+
+    fn nodes(segments: &[Segment]) -> Vec<(Option<i64>, Option<i64>)> {
+        segments
+            .iter()
+            .map(|s| (s.start_node, s.end_node))
+            .collect()
+    }
+
+    /// A trail mapped as three consecutive ways, the last one drawn the other way round.
+    fn chain() -> Vec<Segment> {
+        vec![
+            Segment::path(1, Some(1), Some(2), &[(5.0, 44.7), (5.0, 44.701)]),
+            Segment::path(2, Some(2), Some(3), &[(5.0, 44.701), (5.0, 44.7025)]),
+            Segment::path(3, Some(4), Some(3), &[(5.0, 44.7035), (5.0, 44.7025)]),
+        ]
+    }
+
+    #[test]
+    fn joins_a_chain_of_ways() {
+        let (segments, joins) = merge(chain(), true);
+        assert_eq!(joins, 2);
+        assert_eq!(nodes(&segments), [(Some(1), Some(4))]);
+        let lats: Vec<_> = segments[0].geometry.0.iter().map(|c| c.y).collect();
+        assert_eq!(lats, [44.7, 44.701, 44.7025, 44.7035]);
+        // The longest piece gives the way id
+        assert_eq!(segments[0].way_id, 2);
+    }
+
+    #[test]
+    fn keeps_ways_with_other_tags_apart() {
+        let mut ways = chain();
+        ways[1] = tagged(ways[1].clone(), "track");
+
+        let (segments, joins) = merge(ways.clone(), true);
+        assert_eq!(joins, 0);
+        assert_eq!(segments.len(), 3);
+
+        let (segments, joins) = merge(ways, false);
+        assert_eq!(joins, 2);
+        assert_eq!(segments[0].way_id, 2);
+        assert_eq!(segments[0].tags["highway"], "track");
+    }
+
+    #[test]
+    fn takes_the_way_id_from_the_longest_way_whatever_the_join_order() {
+        // 100 m, then two 60 m ways; the junction between the short ones goes first
+        let ways = vec![
+            Segment::path(1, Some(1), Some(3), &[(5.0, 44.7), (5.0, 44.7009)]),
+            Segment::path(2, Some(3), Some(2), &[(5.0, 44.7009), (5.0, 44.70144)]),
+            Segment::path(3, Some(2), Some(4), &[(5.0, 44.70144), (5.0, 44.70198)]),
+        ];
+        let (segments, joins) = merge(ways.clone(), true);
+        assert_eq!(joins, 2);
+        assert_eq!(segments[0].way_id, 1);
+
+        // Also when the first pass joined the short ones and a sliver's removal joins the rest
+        let mut ways = ways;
+        ways.push(Segment::path(
+            4,
+            Some(3),
+            Some(5),
+            &[(5.0, 44.7009), (5.00004, 44.7009)],
+        ));
+        let segments = normalize_segments(ways, 10.0, true);
+        assert_eq!(nodes(&segments), [(Some(1), Some(4))]);
+        assert_eq!(segments[0].way_id, 1);
+    }
+
+    #[test]
+    fn joins_a_loop_into_one_segment() {
+        let ways = vec![
+            Segment::path(
+                1,
+                Some(1),
+                Some(2),
+                &[(5.0, 44.7), (5.001, 44.7), (5.001, 44.701)],
+            ),
+            Segment::path(
+                2,
+                Some(2),
+                Some(1),
+                &[(5.001, 44.701), (5.0, 44.701), (5.0, 44.7)],
+            ),
+        ];
+        let (segments, joins) = merge(ways, true);
+        assert_eq!(joins, 1);
+        assert_eq!(nodes(&segments), [(Some(2), Some(2))]);
+        assert_eq!(segments[0].geometry.0.len(), 5);
+
+        // A way closing on itself stays as it is
+        let (segments, joins) = merge(segments, true);
+        assert_eq!(joins, 0);
+        assert_eq!(segments.len(), 1);
+    }
+
+    #[test]
+    fn does_not_join_through_a_three_way_junction() {
+        let ways = vec![
+            Segment::path(1, Some(1), Some(2), &[(5.0, 44.7), (5.0, 44.701)]),
+            Segment::path(2, Some(1), Some(3), &[(5.0, 44.7), (5.001, 44.7)]),
+            Segment::path(3, Some(4), Some(1), &[(4.999, 44.7), (5.0, 44.7)]),
+        ];
+        let (segments, joins) = merge(ways, false);
+        assert_eq!(joins, 0);
+        assert_eq!(segments.len(), 3);
+    }
+
+    #[test]
+    fn drops_slivers_hanging_off_the_network() {
+        let ways = vec![
+            Segment::path(1, Some(1), Some(2), &[(5.0, 44.7), (5.0, 44.701)]),
+            // A stub to a dead end, and one cut by the boundary
+            Segment::path(2, Some(2), Some(3), &[(5.0, 44.701), (5.00004, 44.701)]),
+            Segment::path(3, Some(2), None, &[(5.0, 44.701), (5.0, 44.70104)]),
+        ];
+        let (segments, dropped, contracted) = remove(ways, 10.0);
+        assert_eq!((dropped, contracted), (2, 0));
+        assert_eq!(nodes(&segments), [(Some(1), Some(2))]);
+    }
+
+    #[test]
+    fn contracts_slivers_between_junctions() {
+        let ways = vec![
+            Segment::path(1, Some(10), Some(1), &[(4.999, 44.7), (5.0, 44.7)]),
+            Segment::path(2, Some(11), Some(1), &[(5.0, 44.699), (5.0, 44.7)]),
+            Segment::path(3, Some(1), Some(2), &[(5.0, 44.7), (5.0, 44.70004)]),
+            Segment::path(4, Some(2), Some(12), &[(5.0, 44.70004), (5.001, 44.70004)]),
+            Segment::path(5, Some(2), Some(13), &[(5.0, 44.70004), (5.0, 44.701)]),
+        ];
+        let ways_before = ways.clone();
+        let (segments, dropped, contracted) = remove(ways, 10.0);
+        assert_eq!((dropped, contracted), (0, 1));
+        let mut ends = nodes(&segments);
+        ends.sort();
+        assert_eq!(
+            ends,
+            [
+                (Some(1), Some(12)),
+                (Some(1), Some(13)),
+                (Some(10), Some(1)),
+                (Some(11), Some(1)),
+            ]
+        );
+        // The moved ends now start at node 1's coordinate
+        for segment in segments.iter().filter(|s| s.start_node == Some(1)) {
+            assert_eq!(segment.geometry.0[0], Coord { x: 5.0, y: 44.7 });
+        }
+
+        // Both junctions have three ends, so the sliver goes to node 1 whichever way it runs
+        let mut ways = ways_before;
+        ways[2] = ways[2].clone().reversed();
+        let (reversed, _, _) = remove(ways, 10.0);
+        let mut reversed_ends = nodes(&reversed);
+        reversed_ends.sort();
+        assert_eq!(reversed_ends, ends);
+    }
+
+    #[test]
+    fn joins_what_a_dropped_sliver_leaves_behind() {
+        let ways = vec![
+            Segment::path(1, Some(1), Some(2), &[(5.0, 44.7), (5.0, 44.701)]),
+            Segment::path(2, Some(2), Some(3), &[(5.0, 44.701), (5.0, 44.702)]),
+            Segment::path(3, Some(2), Some(4), &[(5.0, 44.701), (5.00004, 44.701)]),
+        ];
+        let segments = normalize_segments(ways, 10.0, true);
+        assert_eq!(nodes(&segments), [(Some(1), Some(3))]);
+    }
+}
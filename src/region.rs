@@ -0,0 +1,408 @@
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::boundary::{self, Area};
+use crate::config::*;
+use crate::osm;
+use crate::tiles::Provider;
+use crate::trail_filter::TrailFilter;
+
+/// A lat/lon rectangle, in degrees.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Bounds {
+    pub south: f64,
+    pub west: f64,
+    pub north: f64,
+    pub east: f64,
+}
+
+impl Bounds {
+    pub fn contains(&self, lat: f64, lon: f64) -> bool {
+        (self.south..=self.north).contains(&lat) && (self.west..=self.east).contains(&lon)
+    }
+
+    pub fn center_lat(&self) -> f64 {
+        (self.south + self.north) / 2.0
+    }
+}
+
+/// An area whose trails are tracked.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Region {
+    /// Key of the region in the config file, e.g. "saou".
+    #[serde(skip)]
+    pub id: String,
+    /// Shown on the rendered map and in the web UI.
+    pub title: String,
+    /// Rendered extent. Optional with a boundary, which then sets the extent.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bounds: Option<Bounds>,
+    /// Only trails and tracks inside this polygon count.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub boundary: Option<Boundary>,
+    /// Where OSM data for this region is cached [default: data/<id>].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache_dir: Option<String>,
+    #[serde(default = "default_zoom")]
+    pub zoom: u32,
+    #[serde(default)]
+    pub tile_provider: Provider,
+    /// Read trails from this OSM extract (`.osm.pbf` or `.osm` XML) instead of Overpass.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub osm_file: Option<String>,
+    /// Refetch cached trails older than this many days [default: the config's
+    /// `osm_cache_days`, else `OSM_CACHE_MAX_AGE_DAYS`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub osm_cache_days: Option<u64>,
+    /// Overpass API endpoints, tried in order [default: the config's `overpass_urls`, else
+    /// `OVERPASS_URLS`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub overpass_urls: Option<Vec<String>>,
+    /// Which OSM ways are trails [default: the config's `trails`, else the built-in one].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trails: Option<TrailFilter>,
+    /// Segments shorter than this many meters are dropped or merged [default: the config's
+    /// `min_segment_m`, else `MIN_SEGMENT_M`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_segment_m: Option<f64>,
+    /// Only merge segments through a junction of two when their tags match [default: the
+    /// config's `merge_same_tags`, else `MERGE_SAME_TAGS`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub merge_same_tags: Option<bool>,
+}
+
+/// Where a region's boundary polygon comes from.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Boundary {
+    /// A GeoJSON file with `Polygon` or `MultiPolygon` geometries.
+    Geojson(String),
+    /// An OSM boundary relation, e.g. a natural park, fetched once and cached.
+    OsmRelation(i64),
+}
+
+fn default_zoom() -> u32 {
+    DEFAULT_ZOOM
+}
+
+impl Region {
+    pub fn osm_cache_max_age(&self) -> chrono::Duration {
+        let days = self.osm_cache_days.unwrap_or(OSM_CACHE_MAX_AGE_DAYS);
+        i64::try_from(days)
+            .ok()
+            .and_then(chrono::Duration::try_days)
+            .unwrap_or(chrono::Duration::MAX)
+    }
+
+    /// Overpass mirrors to query: `OVERPASS_URL` (comma-separated) if set, else the
+    /// configured ones.
+    pub fn overpass_urls(&self) -> Vec<String> {
+        match std::env::var("OVERPASS_URL") {
+            Ok(list) => list.split(',').map(|u| u.trim().to_string()).collect(),
+            Err(_) => match &self.overpass_urls {
+                Some(urls) => urls.clone(),
+                None => OVERPASS_URLS.iter().map(|u| u.to_string()).collect(),
+            },
+        }
+    }
+
+    pub fn min_segment_m(&self) -> f64 {
+        self.min_segment_m.unwrap_or(MIN_SEGMENT_M)
+    }
+
+    pub fn merge_same_tags(&self) -> bool {
+        self.merge_same_tags.unwrap_or(MERGE_SAME_TAGS)
+    }
+
+    pub fn trail_filter(&self) -> TrailFilter {
+        self.trails.clone().unwrap_or_default()
+    }
+
+    pub fn cache_dir(&self) -> PathBuf {
+        match &self.cache_dir {
+            Some(dir) => PathBuf::from(dir),
+            None => Path::new(DATA_DIR).join(&self.id),
+        }
+    }
+
+    /// Resolve the region's bounds and boundary polygon.
+    pub async fn load_area(&self, client: &reqwest::Client) -> Result<Area> {
+        let boundary = match &self.boundary {
+            None => None,
+            Some(Boundary::Geojson(path)) => Some(boundary::read_geojson(Path::new(path))?),
+            Some(Boundary::OsmRelation(id)) => {
+                let cache_path = self
+                    .cache_dir()
+                    .join(format!("{BOUNDARY_CACHE_PREFIX}-{id}.geojson"));
+                if cache_path.exists() {
+                    Some(boundary::read_geojson(&cache_path)?)
+                } else {
+                    let fetched = osm::fetch_boundary(client, &self.overpass_urls(), *id).await?;
+                    boundary::write_geojson(&cache_path, &fetched)?;
+                    eprintln!("Cached boundary to {}", cache_path.display());
+                    Some(fetched)
+                }
+            }
+        };
+
+        let bounds = match (self.bounds, &boundary) {
+            (Some(bounds), _) => bounds,
+            (None, Some(boundary)) => boundary::bounds_of(boundary)
+                .with_context(|| format!("Boundary of region '{}' is empty", self.id))?,
+            (None, None) => bail!("Region '{}' has neither bounds nor a boundary", self.id),
+        };
+        Ok(Area { bounds, boundary })
+    }
+}
+
+/// The regions config file (TOML), one `[regions.<id>]` table per region.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct RegionConfig {
+    /// Region used when `--region` isn't given.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default: Option<String>,
+    /// Cache age in days for regions that don't set their own.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub osm_cache_days: Option<u64>,
+    /// Overpass mirrors for regions that don't set their own.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub overpass_urls: Option<Vec<String>>,
+    /// Trail filter for regions that don't set their own.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trails: Option<TrailFilter>,
+    /// Segment length and merge settings for regions that don't set their own.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_segment_m: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub merge_same_tags: Option<bool>,
+    #[serde(default)]
+    pub regions: BTreeMap<String, Region>,
+}
+
+impl RegionConfig {
+    pub fn load(path: &str) -> Result<Self> {
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read region config {path}"))?;
+        let mut config: Self =
+            toml::from_str(&text).with_context(|| format!("Failed to parse {path}"))?;
+
+        if let Some(trails) = &config.trails {
+            trails
+                .validate()
+                .with_context(|| format!("Invalid [trails] in {path}"))?;
+        }
+        for (id, region) in &mut config.regions {
+            region.id = id.clone();
+            match &region.trails {
+                Some(trails) => trails
+                    .validate()
+                    .with_context(|| format!("Invalid trails of region '{id}' in {path}"))?,
+                None => region.trails = config.trails.clone(),
+            }
+            region.osm_cache_days = region.osm_cache_days.or(config.osm_cache_days);
+            region.overpass_urls = region
+                .overpass_urls
+                .take()
+                .or_else(|| config.overpass_urls.clone());
+            region.min_segment_m = region.min_segment_m.or(config.min_segment_m);
+            region.merge_same_tags = region.merge_same_tags.or(config.merge_same_tags);
+            match region.bounds {
+                Some(b) if !(b.south < b.north && b.west < b.east) => bail!(
+                    "Region '{id}' in {path} has empty bounds (south must be below north, west left of east)"
+                ),
+                None if region.boundary.is_none() => {
+                    bail!("Region '{id}' in {path} needs bounds or a boundary")
+                }
+                _ => {}
+            }
+        }
+        if let Some(default) = &config.default
+            && !config.regions.contains_key(default)
+        {
+            bail!("Default region '{default}' is not defined in {path}");
+        }
+        Ok(config)
+    }
+
+    /// Pick a region by id, else the default one, else the only one.
+    pub fn region(&self, id: Option<&str>) -> Result<&Region> {
+        let id = match (id, &self.default) {
+            (Some(id), _) => id,
+            (None, Some(default)) => default,
+            (None, None) if self.regions.is_empty() => bail!("No regions are configured"),
+            (None, None) if self.regions.len() == 1 => self.regions.keys().next().unwrap(),
+            (None, None) => bail!(
+                "Several regions are configured ({}); choose one with --region",
+                self.ids()
+            ),
+        };
+        self.regions
+            .get(id)
+            .with_context(|| format!("Unknown region '{id}' (configured: {})", self.ids()))
+    }
+
+    /// Append a `[regions.<id>]` table for `region` to the config file, creating it if
+    /// needed. Appending rather than rewriting keeps the file's comments and layout.
+    pub fn append_region(path: &str, region: &Region) -> Result<()> {
+        let string = |s: &str| toml::Value::String(s.to_string()).to_string();
+
+        let mut table = format!(
+            "[regions.{}]\ntitle = {}\n",
+            region.id,
+            string(&region.title)
+        );
+        if let Some(b) = region.bounds {
+            table += &format!(
+                "bounds = {{ south = {:.5}, west = {:.5}, north = {:.5}, east = {:.5} }}\n",
+                b.south, b.west, b.north, b.east
+            );
+        }
+        match &region.boundary {
+            Some(Boundary::Geojson(file)) => {
+                table += &format!("boundary = {{ geojson = {} }}\n", string(file))
+            }
+            Some(Boundary::OsmRelation(id)) => {
+                table += &format!("boundary = {{ osm_relation = {id} }}\n")
+            }
+            None => {}
+        }
+        table += &format!("zoom = {}\n", region.zoom);
+
+        let mut text = fs::read_to_string(path).unwrap_or_default();
+        if !text.is_empty() {
+            text += if text.ends_with('\n') { "\n" } else { "\n\n" };
+        }
+        text += &table;
+        fs::write(path, text).with_context(|| format!("Failed to write {path}"))
+    }
+
+    fn ids(&self) -> String {
+        self.regions.keys().cloned().collect::<Vec<_>>().join(", ")
+    }
+}
+
+/// Whether `id` can be used as a region id: lowercase ASCII letters, digits, `-` and `_`,
+/// so it is a bare TOML key and a safe file name.
+pub fn is_valid_id(id: &str) -> bool {
+    !id.is_empty()
+        && id
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_')
+}
+
+/// A config key for a place name: lowercase ASCII letters and digits joined by dashes,
+/// with accents dropped ("Forêt de Saou" → "foret-de-saou").
+pub fn slug(name: &str) -> String {
+    let folded: String = name
+        .chars()
+        .map(|c| match c.to_lowercase().next().unwrap_or(c) {
+            'à' | 'á' | 'â' | 'ã' | 'ä' | 'å' => 'a',
+            'ç' => 'c',
+            'è' | 'é' | 'ê' | 'ë' => 'e',
+            'ì' | 'í' | 'î' | 'ï' => 'i',
+            'ñ' => 'n',
+            'ò' | 'ó' | 'ô' | 'õ' | 'ö' | 'ø' => 'o',
+            'ù' | 'ú' | 'û' | 'ü' => 'u',
+            'ý' | 'ÿ' => 'y',
+            c if c.is_ascii_alphanumeric() => c,
+            _ => ' ',
+        })
+        .collect();
+    folded.split_whitespace().collect::<Vec<_>>().join("-")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn slugs_place_names() {
+        assert_eq!(slug("Forêt de Saou"), "foret-de-saou");
+        assert_eq!(
+            slug("  Massif du Mont-Blanc (Haute-Savoie) "),
+            "massif-du-mont-blanc-haute-savoie"
+        );
+        assert_eq!(
+            slug("Ñuñoa, Región Metropolitana"),
+            "nunoa-region-metropolitana"
+        );
+        assert_eq!(slug("東京"), "");
+        assert!(is_valid_id(&slug("Forêt de Saou")));
+    }
+
+    #[test]
+    fn accepts_only_bare_key_ids() {
+        assert!(is_valid_id("saou"));
+        assert!(is_valid_id("vercors_2-north"));
+        for id in ["", "foo.bar", "my park", "Saou", "saou\"]", "forêt"] {
+            assert!(!is_valid_id(id), "{id}");
+        }
+    }
+
+    #[test]
+    fn inherits_overpass_mirrors() {
+        let path =
+            std::env::temp_dir().join(format!("synclinal-mirrors-{}.toml", std::process::id()));
+        let path = path.to_str().unwrap();
+        fs::write(
+            path,
+            r#"overpass_urls = ["http://localhost:12345/api/interpreter"]
+
+[regions.saou]
+title = "Saou"
+bounds = { south = 44.6, west = 5.0, north = 44.7, east = 5.2 }
+
+[regions.vercors]
+title = "Vercors"
+bounds = { south = 44.8, west = 5.3, north = 45.2, east = 5.7 }
+overpass_urls = ["https://overpass.private.coffee/api/interpreter"]
+"#,
+        )
+        .unwrap();
+        let config = RegionConfig::load(path).unwrap();
+        fs::remove_file(path).unwrap();
+
+        let urls = |id| config.regions[id].overpass_urls.clone().unwrap();
+        assert_eq!(urls("saou"), ["http://localhost:12345/api/interpreter"]);
+        assert_eq!(
+            urls("vercors"),
+            ["https://overpass.private.coffee/api/interpreter"]
+        );
+    }
+
+    #[test]
+    fn appends_a_region_the_config_can_load() {
+        let path =
+            std::env::temp_dir().join(format!("synclinal-regions-{}.toml", std::process::id()));
+        let path = path.to_str().unwrap();
+        fs::write(
+            path,
+            "# My regions\ndefault = \"saou\"\n\n[regions.saou]\ntitle = \"Saou\"\nbounds = { south = 44.6, west = 5.0, north = 44.7, east = 5.2 }",
+        )
+        .unwrap();
+
+        let mut region: Region = toml::from_str(
+            "title = 'Forêt \"de\" Saou'\nzoom = 13\nboundary = { osm_relation = 7436437 }",
+        )
+        .unwrap();
+        region.id = "foret-de-saou".to_string();
+        RegionConfig::append_region(path, &region).unwrap();
+
+        let text = fs::read_to_string(path).unwrap();
+        let config = RegionConfig::load(path).unwrap();
+        fs::remove_file(path).unwrap();
+
+        assert!(text.starts_with("# My regions\n"));
+        assert_eq!(config.ids(), "foret-de-saou, saou");
+        let added = config.region(Some("foret-de-saou")).unwrap();
+        assert_eq!(added.title, "Forêt \"de\" Saou");
+        assert_eq!(added.zoom, 13);
+        assert!(matches!(
+            added.boundary,
+            Some(Boundary::OsmRelation(7436437))
+        ));
+    }
+}
@@ -10,12 +10,13 @@ pub fn render_png(
     tile_map: &TileMap,
     segments: &[Segment],
     coverage: &[SegmentCoverage],
+    title: &str,
     output_path: &str,
 ) -> Result<()> {
     let w = tile_map.width;
     let h = tile_map.height;
 
-    let svg_content = build_svg_overlay(tile_map, segments, coverage, w, h);
+    let svg_content = build_svg_overlay(tile_map, segments, coverage, title, w, h);
 
     let overlay = rasterize_svg(&svg_content)?;
     let composite = composite_images(&tile_map.image, &overlay);
@@ -36,6 +37,7 @@ fn build_svg_overlay(
     tile_map: &TileMap,
     segments: &[Segment],
     coverage: &[SegmentCoverage],
+    title: &str,
     w: u32,
     h: u32,
 ) -> String {
@@ -89,13 +91,16 @@ fn build_svg_overlay(
     };
     let stats_text = format!("{covered_km:.1} km / {total_km:.1} km ({pct:.0}%)");
 
-    // Title bar
-    svg.push_str(
-        r##"<rect x="10" y="10" width="380" height="40" rx="5" fill="black" fill-opacity="0.6"/>"##,
-    );
-    svg.push_str(
-        r##"<text x="20" y="37" font-family="sans-serif" font-size="18" fill="white" font-weight="bold">Synclinal de Saou — Trail Coverage</text>"##,
-    );
+    // Title bar, sized for ~11 px per bold character
+    let title = format!("{title} — Trail Coverage");
+    let title_w = 20 + title.chars().count() as u32 * 11;
+    svg.push_str(&format!(
+        r##"<rect x="10" y="10" width="{title_w}" height="40" rx="5" fill="black" fill-opacity="0.6"/>"##,
+    ));
+    svg.push_str(&format!(
+        r##"<text x="20" y="37" font-family="sans-serif" font-size="18" fill="white" font-weight="bold">{}</text>"##,
+        escape_xml(&title),
+    ));
 
     // Stats box
     let stats_box_w = 200;
@@ -193,6 +198,12 @@ pub fn render_debug_png(tile_map: &TileMap, segments: &[Segment], output_path: &
     Ok(())
 }
 
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
 fn linestring_to_path(coords: &[geo_types::Coord<f64>], tile_map: &TileMap) -> Option<String> {
     let points: Vec<(f64, f64)> = coords.iter().map(|c| tile_map.project(c.x, c.y)).collect();
     if points.len() < 2 {
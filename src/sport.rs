@@ -0,0 +1,68 @@
+use crate::config::{SPORT_EXCLUDE, SPORT_INCLUDE};
+
+/// Whether an activity of this sport type should be synced and counted toward coverage.
+///
+/// Activities whose sport type is unknown (e.g. a bare GPX dropped into the activities
+/// directory, or a TCX export's "Other") are kept.
+pub fn is_allowed(sport: Option<&str>) -> bool {
+    let Some(sport) = sport.and_then(normalize) else {
+        return true;
+    };
+    let included = SPORT_INCLUDE.is_empty() || SPORT_INCLUDE.iter().any(|p| matches(p, &sport));
+    included && !SPORT_EXCLUDE.iter().any(|p| matches(p, &sport))
+}
+
+/// Map a sport name from any source onto Garmin's activity type keys,
+/// e.g. Strava "Trail Run" or TCX "Running". `None` for names that say nothing about the
+/// sport: TCX uses "Other" for anything but running and biking, FIT often "generic".
+pub fn normalize(sport: &str) -> Option<String> {
+    let key = sport.trim().to_lowercase().replace([' ', '-'], "_");
+    let garmin_key = match key.as_str() {
+        "" | "other" | "generic" => return None,
+        "run" => "running",
+        "trail_run" => "trail_running",
+        "walk" => "walking",
+        "hike" => "hiking",
+        "ride" | "biking" => "cycling",
+        "mountain_bike_ride" => "mountain_biking",
+        "gravel_ride" => "gravel_cycling",
+        "e_bike_ride" => "e_bike_fitness",
+        "weight_training" => "strength_training",
+        "snowshoe" | "snowshoeing" => "snow_shoe",
+        _ => return Some(key),
+    };
+    Some(garmin_key.to_string())
+}
+
+/// Match a config pattern; a trailing `*` matches any suffix.
+fn matches(pattern: &str, sport: &str) -> bool {
+    match pattern.strip_suffix('*') {
+        Some(prefix) => sport.starts_with(prefix),
+        None => sport == pattern,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn maps_source_names_to_garmin_keys() {
+        assert_eq!(normalize("Trail Run").as_deref(), Some("trail_running"));
+        assert_eq!(normalize("Running").as_deref(), Some("running"));
+        assert_eq!(normalize("snowshoeing").as_deref(), Some("snow_shoe"));
+        assert_eq!(normalize("Other"), None);
+        assert_eq!(normalize("generic"), None);
+    }
+
+    #[test]
+    fn keeps_activities_of_unknown_sport() {
+        assert!(is_allowed(None));
+        assert!(is_allowed(Some("")));
+        assert!(is_allowed(Some("Other")));
+        assert!(is_allowed(Some("generic")));
+        assert!(is_allowed(Some("snowshoeing")));
+        assert!(!is_allowed(Some("treadmill_running")));
+        assert!(!is_allowed(Some("Weight Training")));
+    }
+}
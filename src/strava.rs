@@ -0,0 +1,266 @@
+use anyhow::{Context, Result};
+use std::fs::{self, File};
+use std::io::{BufReader, Read};
+use std::path::Path;
+
+use crate::boundary::Area;
+use crate::fs_util::write_atomic;
+use crate::gpx::TrackFormat;
+use crate::metadata::{ActivityMeta, MetadataStore, Source};
+use crate::sport;
+
+/// One row of `activities.csv` from a Strava bulk export.
+struct ArchiveActivity {
+    id: String,
+    date: String,
+    name: String,
+    activity_type: String,
+    elapsed_time_s: Option<f64>,
+    distance_m: Option<f64>,
+    /// Path inside the archive, e.g. "activities/1234567.fit.gz". Empty for manual entries.
+    filename: String,
+}
+
+/// Import a Strava bulk export zip into the activities directory.
+///
+/// Track files are decompressed and written as `strava-{id}.{ext}`, keeping only
+/// activities with a track in the bbox.
+pub fn import_archive(archive_path: &str, activities_dir: &str, area: &Area) -> Result<()> {
+    let file =
+        File::open(archive_path).with_context(|| format!("Failed to open {archive_path}"))?;
+    let mut archive = zip::ZipArchive::new(BufReader::new(file))
+        .with_context(|| format!("Failed to read {archive_path} as a zip archive"))?;
+
+    let activities = read_activities_csv(&mut archive)?;
+    eprintln!("Found {} activities in {archive_path}", activities.len());
+
+    fs::create_dir_all(activities_dir)?;
+    let mut metadata = MetadataStore::load(activities_dir)?;
+
+    let mut imported = 0;
+    for (i, activity) in activities.iter().enumerate() {
+        let progress = format!("[{}/{}]", i + 1, activities.len());
+        let id = &activity.id;
+
+        if activity.filename.is_empty() {
+            eprintln!(
+                "{progress} Skipping {id} ({}) — no GPS file",
+                activity.activity_type
+            );
+            continue;
+        }
+
+        if !sport::is_allowed(Some(&activity.activity_type)) {
+            eprintln!(
+                "{progress} Skipping {id} ({}) — sport type excluded",
+                activity.activity_type
+            );
+            continue;
+        }
+
+        let archive_name = Path::new(&activity.filename);
+        let Some(format) = TrackFormat::from_path(archive_name) else {
+            eprintln!(
+                "{progress} Skipping {id} — unsupported file {}",
+                activity.filename
+            );
+            continue;
+        };
+
+        let ext = normalized_extension(archive_name);
+        let output = Path::new(activities_dir).join(format!("strava-{id}.{ext}"));
+        let file = format!("strava-{id}.{ext}");
+        if output.exists() {
+            eprintln!("{progress} Already have {file}");
+            // Backfill files imported before their metadata was saved
+            if !metadata.contains(&format!("strava-{id}")) {
+                metadata.insert(activity_meta(activity, file));
+                metadata.checkpoint()?;
+            }
+            continue;
+        }
+
+        let bytes = match read_entry(&mut archive, &activity.filename) {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                eprintln!("{progress} Warning: failed to extract {id}: {e:#}");
+                continue;
+            }
+        };
+
+        match format.read(bytes.as_slice()) {
+            Ok(raw) if raw.touches_area(area) => {}
+            Ok(_) => {
+                eprintln!("{progress} Skipping {id} — {} not in bbox", activity.name);
+                continue;
+            }
+            Err(e) => {
+                eprintln!("{progress} Warning: failed to parse {id}: {e:#}");
+                continue;
+            }
+        }
+
+        eprintln!(
+            "{progress} Importing {id} — {} ({}, {})",
+            activity.name, activity.date, activity.activity_type
+        );
+        write_atomic(&output, &bytes)?;
+        metadata.insert(activity_meta(activity, file));
+        metadata.checkpoint()?;
+        imported += 1;
+    }
+    metadata.save()?;
+
+    eprintln!("Imported {imported} new activities to {activities_dir}");
+    Ok(())
+}
+
+fn activity_meta(activity: &ArchiveActivity, file: String) -> ActivityMeta {
+    ActivityMeta {
+        id: format!("strava-{}", activity.id),
+        name: Some(activity.name.clone()).filter(|n| !n.is_empty()),
+        date: parse_date(&activity.date),
+        sport: Some(activity.activity_type.clone()).filter(|t| !t.is_empty()),
+        distance_m: activity.distance_m,
+        duration_s: activity.elapsed_time_s,
+        source: Source::Strava,
+        file,
+    }
+}
+
+fn read_activities_csv<R: Read + std::io::Seek>(
+    archive: &mut zip::ZipArchive<R>,
+) -> Result<Vec<ArchiveActivity>> {
+    let csv_name = archive
+        .file_names()
+        .find(|n| n.rsplit('/').next() == Some("activities.csv"))
+        .context("No activities.csv in archive")?
+        .to_string();
+    let csv_file = archive.by_name(&csv_name)?;
+
+    let mut reader = csv::Reader::from_reader(csv_file);
+    let headers = reader.headers()?.clone();
+    let column = |name: &str| {
+        headers
+            .iter()
+            .position(|h| h == name)
+            .with_context(|| format!("activities.csv has no '{name}' column"))
+    };
+    let (id_col, date_col, name_col, type_col, time_col, file_col) = (
+        column("Activity ID")?,
+        column("Activity Date")?,
+        column("Activity Name")?,
+        column("Activity Type")?,
+        column("Elapsed Time")?,
+        column("Filename")?,
+    );
+    // "Distance" appears twice: first in the account's display unit (km or miles), later
+    // in meters. Only the meters one is unambiguous; older exports have just the first.
+    let distance_cols: Vec<usize> = headers
+        .iter()
+        .enumerate()
+        .filter(|(_, h)| *h == "Distance")
+        .map(|(i, _)| i)
+        .collect();
+    if distance_cols.len() == 1 {
+        eprintln!(
+            "Warning: activities.csv has a single Distance column, assuming kilometers (wrong if the account uses miles)"
+        );
+    }
+
+    let mut activities = Vec::new();
+    for record in reader.records() {
+        let record = record.context("Failed to parse activities.csv")?;
+        let field = |col: usize| record.get(col).unwrap_or_default().trim().to_string();
+        let number = |col: usize| field(col).replace(',', "").parse::<f64>().ok();
+        let distance_m = match distance_cols.as_slice() {
+            [] => None,
+            [km] => number(*km).map(|d| d * 1000.0),
+            [_, .., meters] => number(*meters),
+        };
+        activities.push(ArchiveActivity {
+            id: field(id_col),
+            date: field(date_col),
+            name: field(name_col),
+            activity_type: field(type_col),
+            elapsed_time_s: number(time_col),
+            distance_m,
+            filename: field(file_col),
+        });
+    }
+
+    Ok(activities)
+}
+
+/// Strava dates look like "Jan 5, 2026, 9:12:34 AM"; return "2026-01-05".
+fn parse_date(date: &str) -> Option<String> {
+    chrono::NaiveDateTime::parse_from_str(date, "%b %d, %Y, %I:%M:%S %p")
+        .ok()
+        .map(|d| d.format("%Y-%m-%d").to_string())
+}
+
+/// Read an archive entry, decompressing `.gz` files.
+fn read_entry<R: Read + std::io::Seek>(
+    archive: &mut zip::ZipArchive<R>,
+    name: &str,
+) -> Result<Vec<u8>> {
+    let entry = archive.by_name(name)?;
+    let mut bytes = Vec::new();
+    if name.ends_with(".gz") {
+        flate2::read::GzDecoder::new(entry).read_to_end(&mut bytes)?;
+    } else {
+        BufReader::new(entry).read_to_end(&mut bytes)?;
+    }
+    Ok(bytes)
+}
+
+/// The file extension once decompressed, e.g. "fit" for "123.fit.gz".
+fn normalized_extension(path: &Path) -> String {
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_ascii_lowercase())
+        .unwrap_or_default();
+    let name = name.strip_suffix(".gz").unwrap_or(&name);
+    name.rsplit_once('.')
+        .map(|(_, ext)| ext.to_string())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Cursor, Write};
+
+    fn archive(csv: &str) -> zip::ZipArchive<Cursor<Vec<u8>>> {
+        let mut writer = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        writer
+            .start_file(
+                "export_123/activities.csv",
+                zip::write::SimpleFileOptions::default(),
+            )
+            .unwrap();
+        writer.write_all(csv.as_bytes()).unwrap();
+        zip::ZipArchive::new(writer.finish().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn prefers_the_meters_distance_column() {
+        // An account displaying miles: the first Distance is 6.21 (miles)
+        let csv = "Activity ID,Activity Date,Activity Name,Activity Type,Elapsed Time,Distance,Filename,Distance\n\
+                   42,\"Jan 5, 2026, 9:12:34 AM\",Crêtes,Trail Run,3600,6.21,activities/42.fit.gz,\"10,000.0\"\n";
+
+        let activities = read_activities_csv(&mut archive(csv)).unwrap();
+
+        assert_eq!(activities[0].distance_m, Some(10_000.0));
+    }
+
+    #[test]
+    fn reads_a_single_distance_column_as_kilometers() {
+        let csv = "Activity ID,Activity Date,Activity Name,Activity Type,Elapsed Time,Distance,Filename\n\
+                   42,\"Jan 5, 2026, 9:12:34 AM\",Crêtes,Run,3600,10.5,activities/42.gpx\n";
+
+        let activities = read_activities_csv(&mut archive(csv)).unwrap();
+
+        assert_eq!(activities[0].distance_m, Some(10_500.0));
+    }
+}
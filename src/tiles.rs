@@ -1,13 +1,17 @@
 use anyhow::{Context, Result};
 use image::{DynamicImage, GenericImage, RgbaImage};
+use serde::{Deserialize, Serialize};
 use std::fs;
 use std::path::Path;
 
 use crate::config::*;
+use crate::region::Bounds;
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum Provider {
     OpenStreetMap,
+    #[default]
     OpenTopoMap,
 }
 
@@ -49,27 +53,31 @@ pub struct TileMap {
     pub image: RgbaImage,
     pub width: u32,
     pub height: u32,
+    /// The area covered by the image.
+    pub bounds: Bounds,
 }
 
 impl TileMap {
     /// Convert WGS84 (lon, lat) to pixel coordinates in the cropped image.
     pub fn project(&self, lon: f64, lat: f64) -> (f64, f64) {
-        let x_frac = (lon - BBOX_WEST) / (BBOX_EAST - BBOX_WEST);
-        let y_frac = (mercator_y(lat) - mercator_y(BBOX_NORTH))
-            / (mercator_y(BBOX_SOUTH) - mercator_y(BBOX_NORTH));
+        let b = &self.bounds;
+        let x_frac = (lon - b.west) / (b.east - b.west);
+        let y_frac =
+            (mercator_y(lat) - mercator_y(b.north)) / (mercator_y(b.south) - mercator_y(b.north));
         (x_frac * self.width as f64, y_frac * self.height as f64)
     }
 }
 
 pub async fn fetch_and_stitch(
     client: &reqwest::Client,
+    bounds: &Bounds,
     zoom: u32,
     provider: Provider,
 ) -> Result<TileMap> {
-    let x_min = lon_to_tile(BBOX_WEST, zoom);
-    let x_max = lon_to_tile(BBOX_EAST, zoom);
-    let y_min = lat_to_tile(BBOX_NORTH, zoom);
-    let y_max = lat_to_tile(BBOX_SOUTH, zoom);
+    let x_min = lon_to_tile(bounds.west, zoom);
+    let x_max = lon_to_tile(bounds.east, zoom);
+    let y_min = lat_to_tile(bounds.north, zoom);
+    let y_max = lat_to_tile(bounds.south, zoom);
 
     let tiles_x = x_max - x_min + 1;
     let tiles_y = y_max - y_min + 1;
@@ -93,10 +101,10 @@ pub async fn fetch_and_stitch(
     }
 
     let n = 2_f64.powi(zoom as i32);
-    let px_left = ((BBOX_WEST / 360.0 + 0.5) * n - x_min as f64) * TILE_SIZE as f64;
-    let px_right = ((BBOX_EAST / 360.0 + 0.5) * n - x_min as f64) * TILE_SIZE as f64;
-    let px_top = (mercator_y(BBOX_NORTH) * n - y_min as f64) * TILE_SIZE as f64;
-    let px_bottom = (mercator_y(BBOX_SOUTH) * n - y_min as f64) * TILE_SIZE as f64;
+    let px_left = ((bounds.west / 360.0 + 0.5) * n - x_min as f64) * TILE_SIZE as f64;
+    let px_right = ((bounds.east / 360.0 + 0.5) * n - x_min as f64) * TILE_SIZE as f64;
+    let px_top = (mercator_y(bounds.north) * n - y_min as f64) * TILE_SIZE as f64;
+    let px_bottom = (mercator_y(bounds.south) * n - y_min as f64) * TILE_SIZE as f64;
 
     let crop_x = px_left.floor() as u32;
     let crop_y = px_top.floor() as u32;
@@ -113,6 +121,7 @@ pub async fn fetch_and_stitch(
         width: cropped.width(),
         height: cropped.height(),
         image: cropped,
+        bounds: *bounds,
     })
 }
 
@@ -155,6 +164,19 @@ async fn fetch_tile(
     Ok(img.to_rgba8())
 }
 
+/// A zoom level for a new region: the highest whose map of `bounds` isn't too large.
+pub fn suggest_zoom(bounds: &Bounds) -> u32 {
+    SUGGESTED_ZOOM_RANGE
+        .rev()
+        .find(|&zoom| {
+            let world_px = TILE_SIZE as f64 * 2_f64.powi(zoom as i32);
+            let width = (bounds.east - bounds.west) / 360.0 * world_px;
+            let height = (mercator_y(bounds.south) - mercator_y(bounds.north)) * world_px;
+            width.max(height) <= SUGGESTED_MAX_PX
+        })
+        .unwrap_or(*SUGGESTED_ZOOM_RANGE.start())
+}
+
 fn lon_to_tile(lon: f64, zoom: u32) -> u32 {
     let n = 2_f64.powi(zoom as i32);
     ((lon / 360.0 + 0.5) * n).floor() as u32
@@ -0,0 +1,207 @@
+use anyhow::{Result, bail};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+
+use crate::config::{TRAIL_EXCLUDE, TRAIL_INCLUDE};
+use crate::hash::fnv1a;
+use crate::region::Bounds;
+
+/// Tag → accepted values; every tag must match. `"*"` accepts any value of the tag.
+pub type TagRule = BTreeMap<String, Vec<String>>;
+
+/// Which OSM ways count as trails: those matching any `include` rule and no `exclude`
+/// rule.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TrailFilter {
+    pub include: Vec<TagRule>,
+    #[serde(default)]
+    pub exclude: Vec<TagRule>,
+}
+
+impl Default for TrailFilter {
+    fn default() -> Self {
+        let rules = |rules: &[&[(&str, &[&str])]]| -> Vec<TagRule> {
+            rules
+                .iter()
+                .map(|rule| {
+                    rule.iter()
+                        .map(|(tag, values)| {
+                            (
+                                tag.to_string(),
+                                values.iter().map(|v| v.to_string()).collect(),
+                            )
+                        })
+                        .collect()
+                })
+                .collect()
+        };
+        Self {
+            include: rules(TRAIL_INCLUDE),
+            exclude: rules(TRAIL_EXCLUDE),
+        }
+    }
+}
+
+impl TrailFilter {
+    pub fn validate(&self) -> Result<()> {
+        if self.include.is_empty() {
+            bail!("The trail filter needs at least one include rule");
+        }
+        for rule in self.include.iter().chain(&self.exclude) {
+            if rule.is_empty() {
+                bail!("Trail filter rules need at least one tag");
+            }
+            if let Some((tag, _)) = rule.iter().find(|(_, values)| values.is_empty()) {
+                bail!("Trail filter rule on '{tag}' has no values (use \"*\" for any value)");
+            }
+        }
+        Ok(())
+    }
+
+    pub fn matches(&self, tags: &HashMap<String, String>) -> bool {
+        let rule_matches = |rule: &TagRule| {
+            rule.iter().all(|(tag, values)| {
+                tags.get(tag).is_some_and(|v| {
+                    values
+                        .iter()
+                        .any(|accepted| accepted == "*" || accepted == v)
+                })
+            })
+        };
+        self.include.iter().any(rule_matches) && !self.exclude.iter().any(rule_matches)
+    }
+
+    /// An Overpass QL query for the matching ways in `bounds`, with their geometry.
+    pub fn overpass_query(&self, bounds: &Bounds) -> String {
+        let bbox = format!(
+            "({},{},{},{})",
+            bounds.south, bounds.west, bounds.north, bounds.east
+        );
+        let mut query = String::from("[out:json][timeout:60];\n(\n");
+        for rule in &self.include {
+            query += &format!("  way{}{bbox};\n", conditions(rule));
+        }
+        query += ")->.trails;\n";
+        if self.exclude.is_empty() {
+            query += ".trails out geom;";
+            return query;
+        }
+        query += "(\n";
+        for rule in &self.exclude {
+            query += &format!("  way.trails{};\n", conditions(rule));
+        }
+        query += ")->.excluded;\n(.trails; - .excluded;);\nout geom;";
+        query
+    }
+
+    /// A short stable key for the filter, so caches of different filters don't mix.
+    pub fn cache_key(&self) -> String {
+        // rules are BTreeMaps, so the serialized filter has a fixed order
+        let json = serde_json::to_string(self).unwrap_or_default();
+        format!("{:016x}", fnv1a(json.as_bytes()))
+    }
+}
+
+fn conditions(rule: &TagRule) -> String {
+    rule.iter()
+        .map(|(tag, values)| {
+            let tag = escape_string(tag);
+            if values.iter().any(|v| v == "*") {
+                format!("[\"{tag}\"]")
+            } else {
+                let alternatives: Vec<String> = values.iter().map(|v| escape_regex(v)).collect();
+                format!("[\"{tag}\"~\"^({})$\"]", alternatives.join("|"))
+            }
+        })
+        .collect()
+}
+
+fn escape_string(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// Escape regex metacharacters, then the result for a quoted Overpass string.
+fn escape_regex(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        if "\\^$.|?*+()[]{}".contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escape_string(&escaped)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filter(toml: &str) -> TrailFilter {
+        toml::from_str(toml).unwrap()
+    }
+
+    fn tags(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    fn bounds() -> Bounds {
+        Bounds {
+            south: 44.6,
+            west: 5.0,
+            north: 44.7,
+            east: 5.2,
+        }
+    }
+
+    #[test]
+    fn matches_any_include_and_no_exclude_rule() {
+        let default = TrailFilter::default();
+        assert!(default.matches(&tags(&[("highway", "path")])));
+        assert!(default.matches(&tags(&[("highway", "track"), ("access", "yes")])));
+        assert!(!default.matches(&tags(&[("highway", "residential")])));
+        assert!(!default.matches(&tags(&[("highway", "path"), ("foot", "private")])));
+        assert!(!default.matches(&tags(&[("name", "Sentier")])));
+
+        let any_route = filter(r#"include = [{ route = ["*"], network = ["lwn"] }]"#);
+        assert!(any_route.matches(&tags(&[("route", "hiking"), ("network", "lwn")])));
+        assert!(!any_route.matches(&tags(&[("route", "hiking")])));
+    }
+
+    #[test]
+    fn rejects_empty_rules() {
+        assert!(TrailFilter::default().validate().is_ok());
+        let error = |toml: &str| filter(toml).validate().unwrap_err().to_string();
+        assert!(error("include = []").contains("at least one include rule"));
+        assert!(error("include = [{}]").contains("at least one tag"));
+        let no_values = "include = [{ highway = [\"path\"] }]\nexclude = [{ access = [] }]";
+        assert!(error(no_values).contains("'access' has no values"));
+    }
+
+    #[test]
+    fn builds_the_default_query() {
+        let query = TrailFilter::default().overpass_query(&bounds());
+        assert_eq!(
+            query,
+            "[out:json][timeout:60];\n\
+             (\n  way[\"highway\"~\"^(path|track|footway|bridleway|steps|cycleway|via_ferrata)$\"]\
+             (44.6,5,44.7,5.2);\n)->.trails;\n\
+             (\n  way.trails[\"access\"~\"^(no|private)$\"];\n  \
+             way.trails[\"foot\"~\"^(no|private)$\"];\n  \
+             way.trails[\"abandoned\"~\"^(yes)$\"];\n)->.excluded;\n\
+             (.trails; - .excluded;);\nout geom;"
+        );
+    }
+
+    #[test]
+    fn escapes_quotes_and_regex_characters() {
+        let query = filter(r#"include = [{ 'na"me' = ["*"], ref = ['GR "5"', "a|b.c"] }]"#)
+            .overpass_query(&bounds());
+        assert!(
+            query.contains(r#"way["na\"me"]["ref"~"^(GR \"5\"|a\\|b\\.c)$"](44.6,5,44.7,5.2);"#)
+        );
+        assert!(query.ends_with(")->.trails;\n.trails out geom;"));
+    }
+}
@@ -0,0 +1,123 @@
+use anyhow::{Context, Result};
+use notify::{EventKind, RecursiveMode, Watcher};
+use std::path::Path;
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+use crate::boundary::Area;
+use crate::config::METADATA_FILENAME;
+use crate::osm::Segment;
+use crate::region::Region;
+use crate::tiles::TileMap;
+use crate::{cleaning, export, gpx, grid, matching, render};
+
+/// Wait this long after the last file event before rebuilding, so a batch of files
+/// (e.g. a sync) triggers one rebuild.
+const DEBOUNCE: Duration = Duration::from_secs(2);
+
+pub struct WatchOptions<'a> {
+    pub region: &'a Region,
+    pub area: &'a Area,
+    pub activities_dir: &'a str,
+    pub json_output: &'a str,
+    pub grid_size: f64,
+    pub smooth: bool,
+    /// Also re-render the PNG, onto this tile background.
+    pub render: Option<(&'a TileMap, &'a str)>,
+}
+
+/// Rebuild outputs now, then again whenever track files in the activities directory change.
+pub async fn watch(segments: &[Segment], options: &WatchOptions<'_>) -> Result<()> {
+    let (tx, mut rx) = mpsc::channel(256);
+    let mut watcher = notify::recommended_watcher(move |res| {
+        let _ = tx.blocking_send(res);
+    })
+    .context("Failed to start file watcher")?;
+    watcher
+        .watch(
+            Path::new(options.activities_dir),
+            RecursiveMode::NonRecursive,
+        )
+        .with_context(|| format!("Failed to watch {}", options.activities_dir))?;
+
+    rebuild(segments, options);
+    eprintln!(
+        "Watching {} for new activities (Ctrl-C to stop)",
+        options.activities_dir
+    );
+
+    while let Some(event) = rx.recv().await {
+        let mut changed = is_relevant(event);
+
+        // Keep collecting events until the directory has been quiet for a while
+        while let Ok(Some(event)) = tokio::time::timeout(DEBOUNCE, rx.recv()).await {
+            changed |= is_relevant(event);
+        }
+
+        if changed {
+            eprintln!("Activities changed, rebuilding...");
+            rebuild(segments, options);
+        }
+    }
+
+    Ok(())
+}
+
+/// Whether an event touches a track file or the metadata index.
+fn is_relevant(event: notify::Result<notify::Event>) -> bool {
+    let event = match event {
+        Ok(event) => event,
+        Err(e) => {
+            eprintln!("Warning: file watcher error: {e}");
+            return false;
+        }
+    };
+    if !matches!(
+        event.kind,
+        EventKind::Create(_) | EventKind::Modify(_) | EventKind::Remove(_)
+    ) {
+        return false;
+    }
+    event.paths.iter().any(|path| {
+        let name = path
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_default();
+        if name.starts_with('.') {
+            return false;
+        }
+        name == METADATA_FILENAME
+            || gpx::TrackFormat::from_path(path).is_some()
+            || (path.is_file() && matches!(gpx::detect_format(path), Ok(Some(_))))
+    })
+}
+
+/// Recompute coverage and rewrite the outputs, reporting (not propagating) failures so
+/// the watcher keeps running.
+fn rebuild(segments: &[Segment], options: &WatchOptions) {
+    if let Err(e) = try_rebuild(segments, options) {
+        eprintln!("Warning: rebuild failed: {e:#}");
+    }
+}
+
+fn try_rebuild(segments: &[Segment], options: &WatchOptions) -> Result<()> {
+    let region = options.region;
+    let activities = cleaning::clean_activities(
+        gpx::load_activities(options.activities_dir, std::slice::from_ref(options.area))?,
+        options.smooth,
+    );
+    let coverage = matching::compute_coverage(segments, &activities);
+    let grid_result = grid::compute_grid(segments, &coverage, options.grid_size, options.area);
+    export::export_json(
+        segments,
+        &coverage,
+        &grid_result,
+        region,
+        options.area,
+        options.json_output,
+    )?;
+    if let Some((tile_map, png_output)) = options.render {
+        render::render_png(tile_map, segments, &coverage, &region.title, png_output)?;
+    }
+    Ok(())
+}